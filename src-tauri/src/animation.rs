@@ -0,0 +1,939 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use color_quant::NeuQuant;
+use gif::{DisposalMethod, Repeat};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::imageops::FilterType;
+use image::metadata::LoopCount;
+use image::{AnimationDecoder, DynamicImage, Frame, ImageFormat, ImageResult, RgbaImage};
+
+use crate::errors::{AlicError, AlicErrorType};
+use crate::{avis, marker};
+
+/// An animated GIF, PNG or WebP, with every frame composited onto the full
+/// canvas.
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    // How many times the animation plays, 0 for forever
+    pub plays: u32,
+    // The format it was decoded from
+    pub format: ImageFormat,
+    // What a GIF source says about each frame, which compositing loses
+    pub gif: Option<GifSource>,
+}
+
+pub struct GifSource {
+    // RGB triples
+    pub global_palette: Option<Vec<u8>>,
+    pub frames: Vec<GifFrame>,
+}
+
+pub struct GifFrame {
+    pub dispose: DisposalMethod,
+    pub rect: Rect,
+}
+
+/// Left, top, width and height on the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect(u32, u32, u32, u32);
+
+impl Rect {
+    fn union(self, other: Rect) -> Rect {
+        let (left, top) = (self.0.min(other.0), self.1.min(other.1));
+        let right = (self.0 + self.2).max(other.0 + other.2);
+        let bottom = (self.1 + self.3).max(other.1 + other.3);
+        Rect(left, top, right - left, bottom - top)
+    }
+
+    fn indices(self, canvas_width: u32) -> impl Iterator<Item = usize> {
+        (self.1..self.1 + self.3).flat_map(move |y| {
+            (self.0..self.0 + self.2).map(move |x| (y * canvas_width + x) as usize)
+        })
+    }
+}
+
+impl Animation {
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames
+            .first()
+            .map(|frame| frame.buffer().dimensions())
+            .unwrap_or_default()
+    }
+
+    /// Resize every frame to exactly `width` by `height`.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (src_width, src_height) = self.dimensions();
+        for frame in &mut self.frames {
+            let resized = DynamicImage::ImageRgba8(frame.buffer().clone())
+                .resize_exact(width, height, FilterType::Lanczos3)
+                .into_rgba8();
+            *frame = Frame::from_parts(resized, 0, 0, frame.delay());
+        }
+        // Round frame areas outwards so they still cover what they did
+        let (scale_x, scale_y) = (
+            width as f64 / src_width as f64,
+            height as f64 / src_height as f64,
+        );
+        for frame in self.gif.iter_mut().flat_map(|gif| &mut gif.frames) {
+            let Rect(x, y, w, h) = frame.rect;
+            let left = ((x as f64 * scale_x).floor() as u32).min(width - 1);
+            let top = ((y as f64 * scale_y).floor() as u32).min(height - 1);
+            let right = (((x + w) as f64 * scale_x).ceil() as u32).clamp(left + 1, width);
+            let bottom = (((y + h) as f64 * scale_y).ceil() as u32).clamp(top + 1, height);
+            frame.rect = Rect(left, top, right - left, bottom - top);
+        }
+    }
+
+    /// How long `frame` shows for, in milliseconds. Browsers play GIF delays
+    /// of 10 ms or less at 100 ms, so keep that speed in other formats.
+    fn duration_ms(&self, frame: &Frame) -> u32 {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        match numer / denom.max(1) {
+            0..=10 if self.format == ImageFormat::Gif => 100,
+            delay => delay,
+        }
+    }
+
+    /// The GIF delay for `frame`, in hundredths of a second. Delays from GIFs
+    /// are kept as they are, others are kept above the 10 ms that browsers
+    /// slow down.
+    fn gif_delay(&self, frame: &Frame) -> u16 {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay = (numer / denom.max(1) / 10).min(u16::MAX.into()) as u16;
+        match self.format {
+            ImageFormat::Gif => delay,
+            _ => delay.max(2),
+        }
+    }
+}
+
+/// Whether `data` is a GIF, PNG or WebP with more than one frame. Only the
+/// container is read, no pixels are decoded.
+pub fn is_animated(data: &[u8]) -> bool {
+    match image::guess_format(data) {
+        Ok(ImageFormat::Gif) => gif_frame_count(data, 2) > 1,
+        // acTL starts with the number of frames
+        Ok(ImageFormat::Png) => marker::png_chunks(data).any(|(kind, body)| {
+            kind == b"acTL"
+                && body
+                    .get(..4)
+                    .is_some_and(|frames| u32::from_be_bytes(frames.try_into().unwrap()) > 1)
+        }),
+        Ok(ImageFormat::WebP) => {
+            webp::BitstreamFeatures::new(data).is_some_and(|features| features.has_animation())
+        }
+        _ => false,
+    }
+}
+
+/// Count GIF image descriptors up to `limit` by walking the blocks.
+fn gif_frame_count(data: &[u8], limit: usize) -> usize {
+    // The global color table follows the header and screen descriptor
+    let Some(&flags) = data.get(10) else {
+        return 0;
+    };
+    let mut offset = 13 + color_table_len(flags);
+    let mut frames = 0;
+    while frames < limit {
+        match data.get(offset) {
+            // Extension introducer and label, then its sub-blocks
+            Some(0x21) => offset = skip_sub_blocks(data, offset + 2),
+            Some(0x2C) => {
+                let Some(&flags) = data.get(offset + 9) else {
+                    break;
+                };
+                frames += 1;
+                // Descriptor, local color table and LZW code size, then
+                // the image data
+                offset = skip_sub_blocks(data, offset + 10 + color_table_len(flags) + 1);
+            }
+            _ => break,
+        }
+    }
+    frames
+}
+
+fn color_table_len(flags: u8) -> usize {
+    match flags & 0x80 {
+        0 => 0,
+        _ => 3 << ((flags & 0x07) + 1),
+    }
+}
+
+fn skip_sub_blocks(data: &[u8], mut offset: usize) -> usize {
+    while let Some(&len) = data.get(offset) {
+        offset += 1 + len as usize;
+        if len == 0 {
+            break;
+        }
+    }
+    offset
+}
+
+pub fn decode(data: &[u8]) -> Result<Animation, AlicError> {
+    let cursor = Cursor::new(data);
+    let format = image::guess_format(data).map_err(read_error)?;
+    let (frames, loop_count) = match format {
+        ImageFormat::Gif => read_frames(GifDecoder::new(cursor).map_err(read_error)?),
+        ImageFormat::Png => read_frames(
+            PngDecoder::new(cursor)
+                .and_then(|d| d.apng())
+                .map_err(read_error)?,
+        ),
+        ImageFormat::WebP => read_frames(WebPDecoder::new(cursor).map_err(read_error)?),
+        _ => {
+            return Err(AlicError {
+                error: format!("{format:?} images can't be animated"),
+                error_type: AlicErrorType::UnsupportedFileType,
+            });
+        }
+    }
+    .map_err(read_error)?;
+    if frames.is_empty() {
+        return Err(AlicError {
+            error: "Animation has no frames".to_string(),
+            error_type: AlicErrorType::ImageResizeError,
+        });
+    }
+    let plays = match loop_count {
+        LoopCount::Infinite => 0,
+        LoopCount::Finite(n) => n.get(),
+    };
+    let mut animation = Animation {
+        frames,
+        plays,
+        format,
+        gif: None,
+    };
+    if format == ImageFormat::Gif {
+        read_gif_source(data, &mut animation).map_err(read_error)?;
+    }
+    Ok(animation)
+}
+
+/// Read the loop count, palette and frame disposal straight from the GIF.
+/// The image crate treats a GIF without a loop count as looping forever,
+/// but browsers play those once.
+fn read_gif_source(data: &[u8], animation: &mut Animation) -> Result<(), gif::DecodingError> {
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let mut decoder = options.read_info(data)?;
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        frames.push(GifFrame {
+            dispose: frame.dispose,
+            rect: Rect(
+                frame.left.into(),
+                frame.top.into(),
+                frame.width.into(),
+                frame.height.into(),
+            ),
+        });
+    }
+    // GIF counts repeats after the first play
+    animation.plays = match decoder.repeat() {
+        Repeat::Infinite => 0,
+        Repeat::Finite(repeats) => u32::from(repeats) + 1,
+    };
+    animation.gif = Some(GifSource {
+        global_palette: decoder.global_palette().map(<[u8]>::to_vec),
+        frames,
+    });
+    Ok(())
+}
+
+fn read_frames<'a>(decoder: impl AnimationDecoder<'a>) -> ImageResult<(Vec<Frame>, LoopCount)> {
+    let loop_count = decoder.loop_count();
+    Ok((decoder.into_frames().collect_frames()?, loop_count))
+}
+
+fn read_error(e: impl std::fmt::Display) -> AlicError {
+    AlicError {
+        error: e.to_string(),
+        error_type: AlicErrorType::ImageResizeError,
+    }
+}
+
+fn write_error(e: impl std::fmt::Display) -> AlicError {
+    AlicError {
+        error: e.to_string(),
+        error_type: AlicErrorType::ImageResizeError,
+    }
+}
+
+/// Encode `animation` as `format`, losslessly where the format allows.
+/// `gif_quality` sets how many colors a GIF palette keeps.
+pub fn encode(
+    animation: &Animation,
+    format: ImageFormat,
+    gif_quality: u32,
+) -> Result<Vec<u8>, AlicError> {
+    match format {
+        ImageFormat::Gif => encode_gif(animation, gif_quality),
+        ImageFormat::Png => encode_png(animation),
+        ImageFormat::WebP => {
+            let mut config =
+                webp::WebPConfig::new().map_err(|_| write_error("Error creating WebP config"))?;
+            config.lossless = 1;
+            encode_webp(animation, &config).map_err(write_error)
+        }
+        _ => Err(AlicError {
+            error: format!("{format:?} images can't be animated"),
+            error_type: AlicErrorType::UnsupportedFileType,
+        }),
+    }
+}
+
+/// Encode a GIF that shares one palette across frames. Each frame only
+/// covers what changed since the last one, unchanged pixels inside it are
+/// transparent, and frames that change nothing extend the previous one.
+/// Frames keep the disposal they had in a GIF source.
+fn encode_gif(animation: &Animation, quality: u32) -> Result<Vec<u8>, AlicError> {
+    let (width, height) = animation.dimensions();
+    if width > u16::MAX.into() || height > u16::MAX.into() {
+        return Err(write_error(format!(
+            "{width}x{height} is too large for GIF"
+        )));
+    }
+    // Drawing over a frame can't make its pixels transparent again, so
+    // other formats with transparency clear each frame once it has shown
+    let clears = animation.gif.is_none()
+        && animation
+            .frames
+            .iter()
+            .any(|frame| frame.buffer().pixels().any(|pixel| pixel[3] < 128));
+    let palette = Palette::new(animation, quality);
+    let transparent = palette.transparent;
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut out, width as u16, height as u16, &palette.rgb)
+            .map_err(write_error)?;
+        // A NETSCAPE loop count of 0 means forever, so a GIF that plays
+        // once goes without the extension
+        let repeat = match animation.plays {
+            0 => Some(Repeat::Infinite),
+            1 => None,
+            plays => Some(Repeat::Finite((plays - 1).min(u16::MAX.into()) as u16)),
+        };
+        if let Some(repeat) = repeat {
+            encoder.set_repeat(repeat).map_err(write_error)?;
+        }
+
+        // What's on screen before each frame is drawn, as palette indices
+        let mut screen = vec![transparent; (width * height) as usize];
+        let mut pending: Option<gif::Frame> = None;
+        for (i, frame) in animation.frames.iter().enumerate() {
+            let target = palette.index(frame.buffer());
+            let source = animation.gif.as_ref().and_then(|gif| gif.frames.get(i));
+            let dispose = match source {
+                Some(source) => source.dispose,
+                None if clears => DisposalMethod::Background,
+                None => DisposalMethod::Keep,
+            };
+            let keeps = |dispose| matches!(dispose, DisposalMethod::Any | DisposalMethod::Keep);
+
+            let changed = changed_rect(&screen, &target, width);
+            if changed.is_none()
+                && keeps(dispose)
+                && let Some(previous) = pending.as_mut().filter(|p| keeps(p.dispose))
+            {
+                previous.delay = previous.delay.saturating_add(animation.gif_delay(frame));
+                continue;
+            }
+            let mut rect = changed.unwrap_or(Rect(0, 0, 1, 1));
+            // Clearing to the background covers the frame's whole area
+            if dispose == DisposalMethod::Background
+                && let Some(source) = source
+            {
+                rect = rect.union(source.rect);
+            }
+
+            let pixels: Vec<u8> = rect
+                .indices(width)
+                .map(|i| match target[i] == screen[i] {
+                    true => transparent,
+                    false => target[i],
+                })
+                .collect();
+            if let Some(previous) = pending.replace(gif::Frame {
+                delay: animation.gif_delay(frame),
+                dispose,
+                transparent: Some(transparent),
+                left: rect.0 as u16,
+                top: rect.1 as u16,
+                ..gif::Frame::from_indexed_pixels(rect.2 as u16, rect.3 as u16, pixels, None)
+            }) {
+                encoder.write_frame(&previous).map_err(write_error)?;
+            }
+
+            match dispose {
+                DisposalMethod::Any | DisposalMethod::Keep => {
+                    for i in rect.indices(width) {
+                        if target[i] != transparent {
+                            screen[i] = target[i];
+                        }
+                    }
+                }
+                DisposalMethod::Background => {
+                    for i in rect.indices(width) {
+                        screen[i] = transparent;
+                    }
+                }
+                DisposalMethod::Previous => {}
+            }
+        }
+        if let Some(last) = pending {
+            encoder.write_frame(&last).map_err(write_error)?;
+        }
+    }
+    Ok(out)
+}
+
+/// The smallest area where `after` differs from `before`.
+fn changed_rect(before: &[u8], after: &[u8], width: u32) -> Option<Rect> {
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (i, _) in before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+    {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        left = left.min(x);
+        top = top.min(y);
+        right = right.max(x + 1);
+        bottom = bottom.max(y + 1);
+    }
+    (left < right).then(|| Rect(left, top, right - left, bottom - top))
+}
+
+/// One palette for every frame, with the last entry transparent.
+struct Palette {
+    rgb: Vec<u8>,
+    transparent: u8,
+}
+
+impl Palette {
+    /// Keep a GIF source's global palette when it fits in the colors
+    /// `quality` allows, otherwise quantize every frame down to them.
+    fn new(animation: &Animation, quality: u32) -> Self {
+        let colors = (256 * quality.clamp(1, 100) as usize / 100).max(4);
+        let source = animation
+            .gif
+            .as_ref()
+            .and_then(|gif| gif.global_palette.as_ref())
+            .filter(|palette| palette.len() / 3 < colors);
+        let mut rgb = match source {
+            Some(palette) => palette.clone(),
+            None => {
+                // Sample about a million opaque pixels across all frames
+                let total: usize = animation.frames.iter().map(|f| f.buffer().len() / 4).sum();
+                let step = (total / 1_000_000).max(1);
+                let pixels: Vec<u8> = animation
+                    .frames
+                    .iter()
+                    .flat_map(|frame| frame.buffer().pixels().step_by(step))
+                    .filter(|pixel| pixel[3] >= 128)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                    .collect();
+                match pixels.is_empty() {
+                    true => vec![0, 0, 0],
+                    false => NeuQuant::new(10, colors - 1, &pixels).color_map_rgb(),
+                }
+            }
+        };
+        let transparent = (rgb.len() / 3) as u8;
+        rgb.extend([0, 0, 0]);
+        Self { rgb, transparent }
+    }
+
+    /// Map pixels to their nearest palette entry. GIF transparency is on or
+    /// off, so mostly transparent pixels become fully transparent.
+    fn index(&self, image: &RgbaImage) -> Vec<u8> {
+        let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+        image
+            .pixels()
+            .map(|pixel| {
+                if pixel[3] < 128 {
+                    return self.transparent;
+                }
+                let color = [pixel[0], pixel[1], pixel[2]];
+                *nearest.entry(color).or_insert_with(|| {
+                    self.rgb[..self.transparent as usize * 3]
+                        .chunks_exact(3)
+                        .enumerate()
+                        .min_by_key(|(_, entry)| {
+                            entry
+                                .iter()
+                                .zip(color)
+                                .map(|(a, b)| (i32::from(*a) - i32::from(b)).pow(2))
+                                .sum::<i32>()
+                        })
+                        .map_or(0, |(i, _)| i as u8)
+                })
+            })
+            .collect()
+    }
+}
+
+/// Write an APNG with every frame replacing the last.
+fn encode_png(animation: &Animation) -> Result<Vec<u8>, AlicError> {
+    let (width, height) = animation.dimensions();
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(animation.frames.len() as u32, animation.plays)
+        .map_err(write_error)?;
+    let mut writer = encoder.write_header().map_err(write_error)?;
+    for frame in &animation.frames {
+        // fcTL holds the delay as a fraction of a second in two u16s
+        let duration = animation.duration_ms(frame).min(u16::MAX.into()) as u16;
+        writer
+            .set_frame_delay(duration, 1000)
+            .map_err(write_error)?;
+        writer
+            .write_image_data(frame.buffer())
+            .map_err(write_error)?;
+    }
+    writer.finish().map_err(write_error)?;
+    Ok(out)
+}
+
+/// Encode an animated WebP. This drives libwebp directly because the `webp`
+/// crate's `AnimEncoder` ends the animation at timestamp 0, which makes
+/// libwebp guess the last frame's duration.
+pub fn encode_webp(animation: &Animation, config: &webp::WebPConfig) -> Result<Vec<u8>, String> {
+    use libwebp_sys::*;
+
+    let (width, height) = animation.dimensions();
+    unsafe {
+        let mut options = std::mem::MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+        if WebPAnimEncoderOptionsInitInternal(options.as_mut_ptr(), WebPGetMuxABIVersion()) == 0 {
+            return Err("Error creating WebP animation options".to_string());
+        }
+        let mut options = options.assume_init();
+        // WebP stores the loop count in 16 bits
+        options.anim_params.loop_count = animation.plays.min(u16::MAX.into()) as i32;
+        let encoder = WebPAnimEncoderNewInternal(
+            width as i32,
+            height as i32,
+            &options,
+            WebPGetMuxABIVersion(),
+        );
+        if encoder.is_null() {
+            return Err("Error creating WebP animation encoder".to_string());
+        }
+        let encoder_error = || {
+            let message = WebPAnimEncoderGetError(encoder);
+            match message.is_null() {
+                true => "Error encoding WebP animation".to_string(),
+                false => format!(
+                    "Error encoding WebP animation: {}",
+                    std::ffi::CStr::from_ptr(message).to_string_lossy()
+                ),
+            }
+        };
+
+        let encode_frames = || {
+            // Frames are placed by start time, in milliseconds
+            let mut timestamp = 0;
+            for frame in &animation.frames {
+                let mut picture =
+                    WebPPicture::new().map_err(|_| "Error creating WebP picture".to_string())?;
+                picture.use_argb = 1;
+                picture.width = width as i32;
+                picture.height = height as i32;
+                let added =
+                    WebPPictureImportRGBA(&mut picture, frame.buffer().as_ptr(), width as i32 * 4)
+                        != 0
+                        && WebPAnimEncoderAdd(encoder, &mut picture, timestamp, config) != 0;
+                WebPPictureFree(&mut picture);
+                if !added {
+                    return Err(encoder_error());
+                }
+                timestamp += animation.duration_ms(frame) as i32;
+            }
+            // The end time sets the last frame's duration
+            WebPAnimEncoderAdd(encoder, std::ptr::null_mut(), timestamp, std::ptr::null());
+            let mut data = WebPData::default();
+            if WebPAnimEncoderAssemble(encoder, &mut data) == 0 {
+                return Err(encoder_error());
+            }
+            let encoded = std::slice::from_raw_parts(data.bytes, data.size).to_vec();
+            WebPDataClear(&mut data);
+            Ok(encoded)
+        };
+        let result = encode_frames();
+        WebPAnimEncoderDelete(encoder);
+        result
+    }
+}
+
+/// Settings for an animated AVIF, on the scales ravif takes for still ones.
+pub struct AvifConfig {
+    // 1-100, 100 best
+    pub quality: f32,
+    pub alpha_quality: f32,
+    // 1-10, 1 slowest
+    pub speed: u8,
+    // None for every CPU
+    pub threads: Option<usize>,
+}
+
+/// Encode an animated AVIF as an AV1 image sequence. Colors are converted
+/// the way ravif converts still images, and transparency gets a track of
+/// its own when any frame has some.
+pub fn encode_avif(
+    animation: &Animation,
+    config: &AvifConfig,
+    exif: Option<&[u8]>,
+) -> Result<Vec<u8>, String> {
+    let (width, height) = animation.dimensions();
+    // The sample description stores the size in 16 bits, and rav1e only
+    // encodes sequences from 16 pixels up
+    if width > u16::MAX.into() || height > u16::MAX.into() {
+        return Err(format!("{width}x{height} is too large for animated AVIF"));
+    }
+    if width < 16 || height < 16 {
+        return Err(format!("{width}x{height} is too small for animated AVIF"));
+    }
+    let color = encode_av1(animation, config, false)?;
+    let has_alpha = animation
+        .frames
+        .iter()
+        .any(|frame| frame.buffer().pixels().any(|pixel| pixel[3] < 255));
+    let alpha = match has_alpha {
+        true => Some(encode_av1(animation, config, true)?),
+        false => None,
+    };
+    Ok(avis::write(&avis::Sequence {
+        width,
+        height,
+        // A frame needs some time on screen to be shown at all
+        durations: animation
+            .frames
+            .iter()
+            .map(|frame| animation.duration_ms(frame).max(1))
+            .collect(),
+        plays: animation.plays,
+        color,
+        alpha,
+        exif: exif.map(|exif| exif.to_vec()),
+    }))
+}
+
+/// Encode every frame's color as full range BT.601 4:4:4, or its alpha as
+/// luma only.
+fn encode_av1(
+    animation: &Animation,
+    config: &AvifConfig,
+    alpha: bool,
+) -> Result<avis::Track, String> {
+    use rav1e::color::{
+        ChromaSampling, ColorDescription, ColorPrimaries, MatrixCoefficients, PixelRange,
+        TransferCharacteristics,
+    };
+    use rav1e::prelude::{Config, EncoderConfig, EncoderStatus, FrameType};
+
+    let (width, height) = animation.dimensions();
+    let quality = match alpha {
+        true => config.alpha_quality,
+        false => config.quality,
+    };
+    let quantizer = quality_to_quantizer(quality);
+    let threads = config
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |threads| threads.get()));
+    let mut encoder_config = EncoderConfig::with_speed_preset(config.speed.clamp(1, 10));
+    encoder_config.width = width as usize;
+    encoder_config.height = height as usize;
+    encoder_config.pixel_range = PixelRange::Full;
+    encoder_config.quantizer = quantizer.into();
+    encoder_config.min_quantizer = quantizer;
+    // Tiles let threads share a frame, but tiny ones cost more than they save
+    encoder_config.tiles = threads.min((width * height) as usize / (128 * 128));
+    match alpha {
+        true => encoder_config.chroma_sampling = ChromaSampling::Cs400,
+        false => {
+            encoder_config.chroma_sampling = ChromaSampling::Cs444;
+            encoder_config.color_description = Some(ColorDescription {
+                color_primaries: ColorPrimaries::BT709,
+                transfer_characteristics: TransferCharacteristics::SRGB,
+                matrix_coefficients: MatrixCoefficients::BT601,
+            });
+        }
+    }
+    let encoder_error = |e: &dyn std::fmt::Display| format!("Error encoding AVIF: {e}");
+    let mut context = Config::new()
+        .with_encoder_config(encoder_config)
+        // 0 shares rayon's global pool
+        .with_threads(config.threads.unwrap_or(0))
+        .new_context::<u8>()
+        .map_err(|e| encoder_error(&e))?;
+
+    let stride = width as usize;
+    for frame in &animation.frames {
+        let plane_count = if alpha { 1 } else { 3 };
+        let mut planes: Vec<Vec<u8>> = (0..plane_count)
+            .map(|_| Vec::with_capacity(stride * height as usize))
+            .collect();
+        for pixel in frame.buffer().pixels() {
+            match alpha {
+                true => planes[0].push(pixel[3]),
+                false => {
+                    let [y, u, v] = rgb_to_ycbcr(pixel[0], pixel[1], pixel[2]);
+                    planes[0].push(y);
+                    planes[1].push(u);
+                    planes[2].push(v);
+                }
+            }
+        }
+        let mut input = context.new_frame();
+        for (plane, data) in input.planes.iter_mut().zip(&planes) {
+            plane.copy_from_raw_u8(data, stride, 1);
+        }
+        context.send_frame(input).map_err(|e| encoder_error(&e))?;
+    }
+    context.flush();
+
+    let mut track = avis::Track {
+        config: context.container_sequence_header(),
+        samples: Vec::new(),
+        sync: Vec::new(),
+    };
+    loop {
+        match context.receive_packet() {
+            Ok(packet) => {
+                // Samples in a track don't start with a temporal delimiter
+                let data = match packet.data.strip_prefix(&[0x12, 0x00]) {
+                    Some(data) => data.to_vec(),
+                    None => packet.data,
+                };
+                track.samples.push(data);
+                track.sync.push(packet.frame_type == FrameType::KEY);
+            }
+            // Frames that only feed later ones come out with them
+            Err(EncoderStatus::Encoded) => continue,
+            Err(EncoderStatus::LimitReached) => break,
+            Err(e) => return Err(encoder_error(&e)),
+        }
+    }
+    Ok(track)
+}
+
+/// ravif's mapping from quality to AV1 quantizer, so an animation comes out
+/// like a still image at the same quality.
+fn quality_to_quantizer(quality: f32) -> u8 {
+    let q = quality / 100.0;
+    let x = match q {
+        q if q >= 0.82 => (1.0 - q) * 2.6,
+        q if q > 0.25 => 0.875 - q * 0.5,
+        q => 1.0 - q,
+    };
+    (x * 255.0).round() as u8
+}
+
+/// Full range BT.601, as ravif converts.
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let cb = (b - y) * 0.5 / (1.0 - 0.114) + 128.0;
+    let cr = (r - y) * 0.5 / (1.0 - 0.299) + 128.0;
+    [y.round() as u8, cb.round() as u8, cr.round() as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, Rgba, RgbaImage};
+
+    fn animation(frames: usize, format: ImageFormat) -> Animation {
+        Animation {
+            frames: (0..frames)
+                .map(|i| {
+                    let pixels = RgbaImage::from_pixel(8, 8, Rgba([i as u8 * 60, 0, 0, 255]));
+                    let delay = Delay::from_numer_denom_ms(100 * (i as u32 + 1), 1);
+                    Frame::from_parts(pixels, 0, 0, delay)
+                })
+                .collect(),
+            plays: 3,
+            format,
+            gif: None,
+        }
+    }
+
+    fn encoded(frames: usize, format: ImageFormat) -> Vec<u8> {
+        encode(&animation(frames, format), format, 100)
+            .map_err(|e| e.error)
+            .unwrap()
+    }
+
+    #[test]
+    fn detects_animation() {
+        for format in [ImageFormat::Gif, ImageFormat::Png, ImageFormat::WebP] {
+            assert!(!is_animated(&encoded(1, format)), "{format:?}");
+            assert!(is_animated(&encoded(3, format)), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn round_trips_frames() {
+        for format in [ImageFormat::Gif, ImageFormat::Png, ImageFormat::WebP] {
+            let decoded = decode(&encoded(3, format)).map_err(|e| e.error).unwrap();
+            assert_eq!(decoded.plays, 3, "{format:?}");
+            let delays: Vec<u32> = decoded
+                .frames
+                .iter()
+                .map(|frame| decoded.duration_ms(frame))
+                .collect();
+            assert_eq!(delays, [100, 200, 300], "{format:?}");
+        }
+    }
+
+    /// The bodies of the boxes in `data` with type `kind`.
+    fn boxes<'a>(data: &'a [u8], kind: &[u8]) -> Vec<&'a [u8]> {
+        let mut found = Vec::new();
+        let mut rest = data;
+        while rest.len() >= 8 {
+            let size = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            if &rest[4..8] == kind {
+                found.push(&rest[8..size]);
+            }
+            rest = &rest[size..];
+        }
+        found
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn encodes_avif_sequence() {
+        let config = AvifConfig {
+            quality: 80.0,
+            alpha_quality: 80.0,
+            speed: 10,
+            threads: Some(1),
+        };
+        let mut animation = animation(3, ImageFormat::WebP);
+        animation.resize(16, 16);
+        let avif = encode_avif(&animation, &config, None).unwrap();
+        assert_eq!(&boxes(&avif, b"ftyp")[0][..4], b"avis");
+        let moov = boxes(&avif, b"moov")[0];
+        // Three plays of 600 ms
+        assert_eq!(u32_at(boxes(moov, b"mvhd")[0], 16), 1800);
+        let tracks = boxes(moov, b"trak");
+        assert_eq!(tracks.len(), 1);
+        let stbl = boxes(boxes(boxes(tracks[0], b"mdia")[0], b"minf")[0], b"stbl")[0];
+        // Sample count after the version and the fixed sample size
+        assert_eq!(u32_at(boxes(stbl, b"stsz")[0], 8), 3);
+        // Runs of one frame each, after the version and the run count
+        let stts = boxes(stbl, b"stts")[0];
+        let durations: Vec<u32> = (0..3).map(|i| u32_at(stts, 12 + i * 8)).collect();
+        assert_eq!(durations, [100, 200, 300]);
+
+        // Transparency adds an alpha track, and forever has no end
+        animation.frames[1] =
+            Frame::from_parts(RgbaImage::new(16, 16), 0, 0, animation.frames[1].delay());
+        animation.plays = 0;
+        let avif = encode_avif(&animation, &config, None).unwrap();
+        let moov = boxes(&avif, b"moov")[0];
+        assert_eq!(u32_at(boxes(moov, b"mvhd")[0], 16), u32::MAX);
+        assert_eq!(boxes(moov, b"trak").len(), 2);
+
+        animation.resize(8, 8);
+        assert!(encode_avif(&animation, &config, None).is_err());
+    }
+    /// Frame disposals, delays and whether each has its own palette.
+    fn gif_frames(data: &[u8]) -> (Repeat, Vec<(DisposalMethod, u16, bool)>) {
+        let mut options = gif::DecodeOptions::new();
+        options.skip_frame_decoding(true);
+        let mut decoder = options.read_info(data).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.dispose, frame.delay, frame.palette.is_some()));
+        }
+        (decoder.repeat(), frames)
+    }
+
+    #[test]
+    fn keeps_gif_loop_count_and_disposal() {
+        // Red, blue and green, with a blue square cleared after it shows
+        let mut data = Vec::new();
+        {
+            let palette = [255, 0, 0, 0, 0, 255, 0, 255, 0];
+            // No loop extension, so it plays once
+            let mut encoder = gif::Encoder::new(&mut data, 8, 8, &palette).unwrap();
+            for (color, left, dispose) in [
+                (0, 0, DisposalMethod::Keep),
+                (1, 4, DisposalMethod::Background),
+                (2, 0, DisposalMethod::Keep),
+            ] {
+                let size = if left == 0 && color == 0 { 8 } else { 4 };
+                let pixels = vec![color; (size * size) as usize];
+                let mut frame = gif::Frame::from_indexed_pixels(size, size, pixels, None);
+                frame.left = left;
+                frame.top = left;
+                frame.delay = 10;
+                frame.dispose = dispose;
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+
+        let mut decoded = decode(&data).map_err(|e| e.error).unwrap();
+        assert_eq!(decoded.plays, 1);
+        decoded.resize(4, 4);
+        let resized = encode(&decoded, ImageFormat::Gif, 100)
+            .map_err(|e| e.error)
+            .unwrap();
+        let (repeat, frames) = gif_frames(&resized);
+        assert_eq!(repeat, Repeat::Finite(0));
+        assert_eq!(
+            frames,
+            [
+                (DisposalMethod::Keep, 10, false),
+                (DisposalMethod::Background, 10, false),
+                (DisposalMethod::Keep, 10, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_gif_played_once() {
+        let mut animation = animation(3, ImageFormat::Gif);
+        animation.plays = 1;
+        let data = encode(&animation, ImageFormat::Gif, 100)
+            .map_err(|e| e.error)
+            .unwrap();
+        assert!(!data.windows(11).any(|window| window == b"NETSCAPE2.0"));
+        assert_eq!(decode(&data).map_err(|e| e.error).unwrap().plays, 1);
+
+        animation.plays = 2;
+        let data = encode(&animation, ImageFormat::Gif, 100)
+            .map_err(|e| e.error)
+            .unwrap();
+        assert_eq!(gif_frames(&data).0, Repeat::Finite(1));
+        assert_eq!(decode(&data).map_err(|e| e.error).unwrap().plays, 2);
+    }
+
+    #[test]
+    fn merges_unchanged_gif_frames() {
+        let mut animation = animation(3, ImageFormat::Gif);
+        let (first, _) = animation.frames.split_at_mut(2);
+        first[1] = Frame::from_parts(first[0].buffer().clone(), 0, 0, first[1].delay());
+
+        let data = encode(&animation, ImageFormat::Gif, 100)
+            .map_err(|e| e.error)
+            .unwrap();
+        let (_, frames) = gif_frames(&data);
+        let delays: Vec<u16> = frames.iter().map(|(_, delay, _)| *delay).collect();
+        assert_eq!(delays, [30, 30]);
+    }
+}
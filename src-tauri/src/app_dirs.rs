@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use log::debug;
+use tauri::Manager;
+
+/// The app's folders, resolved by Tauri once the app is set up so the CLI
+/// and compression threads don't need an `AppHandle`.
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Resolve the folders, before anything reads or writes its files there.
+pub fn init(app: &tauri::AppHandle) {
+    match app.path().app_data_dir() {
+        Ok(dir) => {
+            let _ = DATA_DIR.set(dir);
+        }
+        Err(e) => debug!("No app data folder: {e}"),
+    }
+    match app.path().app_cache_dir() {
+        Ok(dir) => {
+            let _ = CACHE_DIR.set(dir);
+        }
+        Err(e) => debug!("No app cache folder: {e}"),
+    }
+}
+
+/// `name` in the app's data folder, or `None` when it couldn't be resolved.
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    DATA_DIR.get().map(|dir| dir.join(name))
+}
+
+/// `name` in the app's cache folder, or `None` when it couldn't be resolved.
+pub fn cache_file(name: &str) -> Option<PathBuf> {
+    CACHE_DIR.get().map(|dir| dir.join(name))
+}
@@ -0,0 +1,403 @@
+//! Writes animated AVIF files: an AV1 image sequence in a `moov` track, with
+//! the first frame also stored as the primary image for decoders that only
+//! read still AVIFs.
+
+const TIMESCALE: u32 = 1000;
+const ALPHA_URN: &[u8] = b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha\0";
+
+/// One track of AV1 samples, one sample per frame.
+pub struct Track {
+    // The av1C record from the encoder
+    pub config: Vec<u8>,
+    // Temporal units without their temporal delimiter
+    pub samples: Vec<Vec<u8>>,
+    // Whether each sample is a key frame that players can start from
+    pub sync: Vec<bool>,
+}
+
+pub struct Sequence {
+    pub width: u32,
+    pub height: u32,
+    // How long each frame shows for, in milliseconds
+    pub durations: Vec<u32>,
+    // How many times the animation plays, 0 for forever
+    pub plays: u32,
+    pub color: Track,
+    // Luma-only samples for transparency, if any frame has it
+    pub alpha: Option<Track>,
+    // A TIFF Exif block
+    pub exif: Option<Vec<u8>>,
+}
+
+/// Build the file. Sample offsets point into `mdat`, which comes last, so
+/// the header is built once to find its length and again with the offsets.
+pub fn write(sequence: &Sequence) -> Vec<u8> {
+    let header_len = header(sequence, 0).len() as u32;
+    // mdat's own size and type come before the samples
+    let mut out = header(sequence, header_len + 8);
+    write_box(&mut out, b"mdat", |out| {
+        for track in [Some(&sequence.color), sequence.alpha.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            track.samples.iter().for_each(|sample| out.extend(sample));
+        }
+        if let Some(exif) = &sequence.exif {
+            out.extend(exif_item(exif));
+        }
+    });
+    out
+}
+
+fn header(sequence: &Sequence, data_start: u32) -> Vec<u8> {
+    let color_len = track_len(&sequence.color);
+    let alpha_len = sequence.alpha.as_ref().map_or(0, track_len);
+    let mut out = Vec::new();
+    write_box(&mut out, b"ftyp", |out| {
+        out.extend(b"avis");
+        put_u32(out, 0);
+        for brand in [b"avif", b"avis", b"msf1", b"iso8", b"mif1", b"miaf"] {
+            out.extend(brand);
+        }
+    });
+    write_meta(&mut out, sequence, data_start, color_len + alpha_len);
+    write_box(&mut out, b"moov", |out| {
+        let media_duration: u32 = sequence.durations.iter().sum();
+        // Players stop after the track's duration, so forever is the
+        // largest duration there is
+        let duration = match sequence.plays {
+            0 => u32::MAX,
+            plays => media_duration.saturating_mul(plays),
+        };
+        let track_count = 1 + sequence.alpha.is_some() as u32;
+        full_box(out, b"mvhd", 0, 0, |out| {
+            put_u32(out, 0);
+            put_u32(out, 0);
+            put_u32(out, TIMESCALE);
+            put_u32(out, duration);
+            // Rate 1.0, volume 1.0 and reserved
+            put_u32(out, 0x0001_0000);
+            out.extend([0x01, 0x00, 0, 0]);
+            out.extend([0; 8]);
+            put_matrix(out);
+            out.extend([0; 24]);
+            put_u32(out, track_count + 1);
+        });
+        write_track(out, sequence, &sequence.color, 1, duration, data_start);
+        if let Some(alpha) = &sequence.alpha {
+            write_track(out, sequence, alpha, 2, duration, data_start + color_len);
+        }
+    });
+    out
+}
+
+fn write_meta(out: &mut Vec<u8>, sequence: &Sequence, data_start: u32, exif_offset: u32) {
+    let first_len = |track: &Track| track.samples.first().map_or(0, |sample| sample.len()) as u32;
+    let alpha_id = sequence.alpha.as_ref().map(|_| 2);
+    let exif_id = sequence.exif.as_ref().map(|_| 3);
+    full_box(out, b"meta", 0, 0, |out| {
+        write_handler(out, b"pict");
+        full_box(out, b"pitm", 0, 0, |out| put_u16(out, 1));
+        full_box(out, b"iloc", 0, 0, |out| {
+            // 4-byte offsets and lengths, no base offset
+            out.extend([0x44, 0x00]);
+            let mut items = vec![(1, data_start, first_len(&sequence.color))];
+            if let Some(alpha) = &sequence.alpha {
+                items.push((2, data_start + track_len(&sequence.color), first_len(alpha)));
+            }
+            if let Some(exif) = &sequence.exif {
+                items.push((3, data_start + exif_offset, exif_item(exif).len() as u32));
+            }
+            put_u16(out, items.len() as u16);
+            for (id, offset, len) in items {
+                put_u16(out, id);
+                put_u16(out, 0);
+                put_u16(out, 1);
+                put_u32(out, offset);
+                put_u32(out, len);
+            }
+        });
+        full_box(out, b"iinf", 0, 0, |out| {
+            let items: Vec<(u16, &[u8; 4])> = [
+                Some((1, b"av01")),
+                alpha_id.map(|id| (id, b"av01")),
+                exif_id.map(|id| (id, b"Exif")),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            put_u16(out, items.len() as u16);
+            for (id, kind) in items {
+                full_box(out, b"infe", 2, 0, |out| {
+                    put_u16(out, id);
+                    put_u16(out, 0);
+                    out.extend(kind);
+                    out.push(0);
+                });
+            }
+        });
+        if alpha_id.is_some() || exif_id.is_some() {
+            full_box(out, b"iref", 0, 0, |out| {
+                for (kind, from) in [(b"auxl", alpha_id), (b"cdsc", exif_id)] {
+                    if let Some(from) = from {
+                        write_box(out, kind, |out| {
+                            put_u16(out, from);
+                            put_u16(out, 1);
+                            put_u16(out, 1);
+                        });
+                    }
+                }
+            });
+        }
+        write_box(out, b"iprp", |out| {
+            write_box(out, b"ipco", |out| {
+                // 1: ispe, 2: av1C, 3: colr, 4: pixi
+                full_box(out, b"ispe", 0, 0, |out| {
+                    put_u32(out, sequence.width);
+                    put_u32(out, sequence.height);
+                });
+                write_box(out, b"av1C", |out| out.extend(&sequence.color.config));
+                write_colr(out);
+                write_pixi(out, 3);
+                // 5: av1C, 6: pixi, 7: auxC
+                if let Some(alpha) = &sequence.alpha {
+                    write_box(out, b"av1C", |out| out.extend(&alpha.config));
+                    write_pixi(out, 1);
+                    full_box(out, b"auxC", 0, 0, |out| out.extend(ALPHA_URN));
+                }
+            });
+            full_box(out, b"ipma", 0, 0, |out| {
+                put_u32(out, 1 + alpha_id.is_some() as u32);
+                // The high bit marks properties decoders must understand
+                put_u16(out, 1);
+                out.extend([4, 1, 0x82, 3, 4]);
+                if let Some(id) = alpha_id {
+                    put_u16(out, id);
+                    out.extend([4, 1, 0x85, 6, 0x87]);
+                }
+            });
+        });
+    });
+}
+
+fn write_track(
+    out: &mut Vec<u8>,
+    sequence: &Sequence,
+    track: &Track,
+    id: u32,
+    duration: u32,
+    data_start: u32,
+) {
+    let is_alpha = id != 1;
+    let media_duration: u32 = sequence.durations.iter().sum();
+    write_box(out, b"trak", |out| {
+        // Enabled and in the movie
+        full_box(out, b"tkhd", 0, 3, |out| {
+            put_u32(out, 0);
+            put_u32(out, 0);
+            put_u32(out, id);
+            put_u32(out, 0);
+            put_u32(out, duration);
+            // Reserved, layer, alternate group, volume and reserved
+            out.extend([0; 16]);
+            put_matrix(out);
+            // 16.16 fixed point
+            put_u32(out, sequence.width << 16);
+            put_u32(out, sequence.height << 16);
+        });
+        if is_alpha {
+            write_box(out, b"tref", |out| {
+                write_box(out, b"auxl", |out| put_u32(out, 1));
+            });
+        }
+        write_box(out, b"edts", |out| {
+            // Flag 1 repeats the edit for as long as the track lasts
+            let flags = (sequence.plays != 1) as u32;
+            full_box(out, b"elst", 0, flags, |out| {
+                put_u32(out, 1);
+                put_u32(out, media_duration);
+                put_u32(out, 0);
+                put_u32(out, 0x0001_0000);
+            });
+        });
+        write_box(out, b"mdia", |out| {
+            full_box(out, b"mdhd", 0, 0, |out| {
+                put_u32(out, 0);
+                put_u32(out, 0);
+                put_u32(out, TIMESCALE);
+                put_u32(out, media_duration);
+                // "und" language
+                put_u16(out, 0x55C4);
+                put_u16(out, 0);
+            });
+            write_handler(out, if is_alpha { b"auxv" } else { b"pict" });
+            write_box(out, b"minf", |out| {
+                full_box(out, b"vmhd", 0, 1, |out| out.extend([0; 8]));
+                write_box(out, b"dinf", |out| {
+                    full_box(out, b"dref", 0, 0, |out| {
+                        put_u32(out, 1);
+                        // The data is in this file
+                        full_box(out, b"url ", 0, 1, |_| {});
+                    });
+                });
+                write_sample_table(out, sequence, track, is_alpha, data_start);
+            });
+        });
+    });
+}
+
+fn write_sample_table(
+    out: &mut Vec<u8>,
+    sequence: &Sequence,
+    track: &Track,
+    is_alpha: bool,
+    data_start: u32,
+) {
+    write_box(out, b"stbl", |out| {
+        full_box(out, b"stsd", 0, 0, |out| {
+            put_u32(out, 1);
+            write_box(out, b"av01", |out| {
+                // Reserved, then data reference 1
+                out.extend([0; 6]);
+                put_u16(out, 1);
+                out.extend([0; 16]);
+                put_u16(out, sequence.width as u16);
+                put_u16(out, sequence.height as u16);
+                // 72 dpi both ways, reserved, one frame per sample
+                put_u32(out, 0x0048_0000);
+                put_u32(out, 0x0048_0000);
+                put_u32(out, 0);
+                put_u16(out, 1);
+                // Compressor name as a 32-byte Pascal string
+                let name = b"AOM Coding";
+                out.push(name.len() as u8);
+                out.extend(name);
+                out.extend(vec![0; 31 - name.len()]);
+                // 24-bit depth, no color table
+                put_u16(out, 0x0018);
+                put_u16(out, 0xFFFF);
+                write_box(out, b"av1C", |out| out.extend(&track.config));
+                if is_alpha {
+                    full_box(out, b"auxi", 0, 0, |out| out.extend(ALPHA_URN));
+                } else {
+                    write_colr(out);
+                }
+                // Frames predict from others, up to 15 references each
+                full_box(out, b"ccst", 0, 0, |out| put_u32(out, 0x7C00_0000));
+            });
+        });
+        full_box(out, b"stts", 0, 0, |out| {
+            // Runs of frames with the same duration
+            let mut runs: Vec<(u32, u32)> = Vec::new();
+            for &duration in &sequence.durations {
+                match runs.last_mut() {
+                    Some((count, last)) if *last == duration => *count += 1,
+                    _ => runs.push((1, duration)),
+                }
+            }
+            put_u32(out, runs.len() as u32);
+            for (count, duration) in runs {
+                put_u32(out, count);
+                put_u32(out, duration);
+            }
+        });
+        full_box(out, b"stss", 0, 0, |out| {
+            let sync: Vec<u32> = (1..)
+                .zip(&track.sync)
+                .filter_map(|(number, &sync)| sync.then_some(number))
+                .collect();
+            put_u32(out, sync.len() as u32);
+            sync.into_iter().for_each(|number| put_u32(out, number));
+        });
+        // Every sample in one chunk
+        full_box(out, b"stsc", 0, 0, |out| {
+            put_u32(out, 1);
+            put_u32(out, 1);
+            put_u32(out, track.samples.len() as u32);
+            put_u32(out, 1);
+        });
+        full_box(out, b"stsz", 0, 0, |out| {
+            put_u32(out, 0);
+            put_u32(out, track.samples.len() as u32);
+            for sample in &track.samples {
+                put_u32(out, sample.len() as u32);
+            }
+        });
+        full_box(out, b"stco", 0, 0, |out| {
+            put_u32(out, 1);
+            put_u32(out, data_start);
+        });
+    });
+}
+
+fn write_handler(out: &mut Vec<u8>, handler: &[u8; 4]) {
+    full_box(out, b"hdlr", 0, 0, |out| {
+        put_u32(out, 0);
+        out.extend(handler);
+        // Reserved, then an empty name
+        out.extend([0; 13]);
+    });
+}
+
+/// sRGB primaries and transfer with BT.601 full range YUV, as ravif writes.
+fn write_colr(out: &mut Vec<u8>) {
+    write_box(out, b"colr", |out| {
+        out.extend(b"nclx");
+        put_u16(out, 1);
+        put_u16(out, 13);
+        put_u16(out, 6);
+        out.push(0x80);
+    });
+}
+
+fn write_pixi(out: &mut Vec<u8>, channels: u8) {
+    full_box(out, b"pixi", 0, 0, |out| {
+        out.push(channels);
+        out.extend(vec![8; channels.into()]);
+    });
+}
+
+/// The Exif item starts with the offset to the TIFF header.
+fn exif_item(exif: &[u8]) -> Vec<u8> {
+    [&[0; 4], exif].concat()
+}
+
+fn track_len(track: &Track) -> u32 {
+    track.samples.iter().map(|sample| sample.len() as u32).sum()
+}
+
+fn put_matrix(out: &mut Vec<u8>) {
+    for value in [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        put_u32(out, value);
+    }
+}
+
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    put_u32(out, 0);
+    out.extend(kind);
+    body(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn full_box(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(out, kind, |out| {
+        put_u32(out, ((version as u32) << 24) | flags);
+        body(out);
+    });
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend(value.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_be_bytes());
+}
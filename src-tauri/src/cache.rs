@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use crate::app_dirs;
+use crate::settings::ProfileData;
+
+const CACHE_FILE: &str = "results.jsonl";
+
+static CACHE: LazyLock<Mutex<Cache>> =
+    LazyLock::new(|| Mutex::new(Cache::open(app_dirs::cache_file(CACHE_FILE))));
+
+/// What happened the last time an image was processed with a profile.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub enum Outcome {
+    // The image couldn't be made smaller
+    NotSmaller,
+    // The image was written to `outputs`
+    Compressed { outputs: Vec<String> },
+    // The image is itself an output of the profile
+    Output,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    key: String,
+    outcome: Outcome,
+}
+
+/// Outcomes keyed by image content and profile, kept in an append-only
+/// JSON Lines file so concurrent writers never corrupt earlier entries.
+pub struct Cache {
+    path: Option<PathBuf>,
+    entries: HashMap<String, Outcome>,
+    // The file ends in a line cut short by a crash
+    torn: bool,
+}
+
+impl Cache {
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let contents = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let mut entries = HashMap::new();
+        for line in contents.lines() {
+            // A line cut short by a crash is skipped, not fatal
+            if let Ok(entry) = serde_json::from_str::<Entry>(line) {
+                entries.insert(entry.key, entry.outcome);
+            }
+        }
+        let torn = !contents.is_empty() && !contents.ends_with('\n');
+        let mut cache = Self {
+            path,
+            entries,
+            torn,
+        };
+        // Later lines replace earlier ones for the same image, so the file
+        // only keeps growing unless they're dropped
+        if contents.lines().count() > cache.entries.len() {
+            cache.compact();
+        }
+        cache
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Outcome> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: String, outcome: Outcome) {
+        if self.entries.get(&key) == Some(&outcome) {
+            return;
+        }
+        if let Some(path) = &self.path {
+            match append(path, &key, &outcome, self.torn) {
+                Ok(()) => self.torn = false,
+                Err(e) => debug!("Could not write cache entry: {e}"),
+            }
+        }
+        self.entries.insert(key, outcome);
+    }
+
+    /// Rewrite the file with one line per entry. The new file is written
+    /// next to it and renamed over it, so the old one stays whole until
+    /// then. An entry another process appends meanwhile is lost, which only
+    /// means processing that image again.
+    fn compact(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let temp_path = path.with_extension(format!("jsonl.{}.tmp", std::process::id()));
+        let result = write_entries(&temp_path, &self.entries)
+            .and_then(|()| fs::rename(&temp_path, path).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.torn = false,
+            Err(e) => debug!("Could not compact the cache: {e}"),
+        }
+    }
+}
+
+fn write_entries(path: &Path, entries: &HashMap<String, Outcome>) -> Result<(), String> {
+    let mut lines = String::new();
+    for (key, outcome) in entries {
+        let entry = Entry {
+            key: key.clone(),
+            outcome: outcome.clone(),
+        };
+        lines.push_str(&serde_json::to_string(&entry).map_err(|e| e.to_string())?);
+        lines.push('\n');
+    }
+    fs::write(path, lines).map_err(|e| e.to_string())
+}
+
+fn append(path: &Path, key: &str, outcome: &Outcome, torn: bool) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let entry = Entry {
+        key: key.to_string(),
+        outcome: outcome.clone(),
+    };
+    let mut line = match torn {
+        true => "\n".to_string(),
+        false => String::new(),
+    };
+    line.push_str(&serde_json::to_string(&entry).map_err(|e| e.to_string())?);
+    line.push('\n');
+    // One write per entry so appends from parallel CLI runs don't interleave
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| e.to_string())
+}
+
+/// Identify `data` processed with `parameters`. Profile fields that don't
+/// change the output (name, id, which profile is active) are left out, and
+/// the app version is mixed in so encoder upgrades start fresh.
+pub fn content_key(data: &[u8], parameters: &ProfileData) -> String {
+    let mut profile = serde_json::to_value(parameters).unwrap_or_default();
+    if let Some(profile) = profile.as_object_mut() {
+        for field in ["name", "id", "active", "should_cache"] {
+            profile.remove(field);
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(profile.to_string());
+    hasher.update(data);
+    hex(&hasher.finalize())
+}
+
+/// Narrow a content key to one source path. Outputs belong to a path, so
+/// an identical copy elsewhere must not be skipped because of them.
+pub fn path_key(content_key: &str, path: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content_key);
+    hasher.update(path);
+    hex(&hasher.finalize())
+}
+
+pub fn lookup(key: &str) -> Option<Outcome> {
+    CACHE.lock().unwrap().get(key).cloned()
+}
+
+pub fn record(key: String, outcome: Outcome) {
+    CACHE.lock().unwrap().insert(key, outcome);
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("alic-cache-{}.jsonl", std::process::id()));
+        let mut cache = Cache::open(Some(path.clone()));
+        cache.insert("a".to_string(), Outcome::Output);
+        cache.insert("a".to_string(), Outcome::NotSmaller);
+        cache.insert(
+            "b".to_string(),
+            Outcome::Compressed {
+                outputs: vec!["b.min.png".to_string()],
+            },
+        );
+        // A torn line from an interrupted write
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"key\":\"c\",\"outc")
+            .unwrap();
+
+        let cache = Cache::open(Some(path.clone()));
+        assert_eq!(cache.get("a"), Some(&Outcome::NotSmaller));
+        assert_eq!(
+            cache.get("b"),
+            Some(&Outcome::Compressed {
+                outputs: vec!["b.min.png".to_string()]
+            })
+        );
+        assert_eq!(cache.get("c"), None);
+        // Replaced and torn lines are gone from the file
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.ends_with('\n'));
+
+        let mut cache = cache;
+        cache.insert("d".to_string(), Outcome::Output);
+        let cache = Cache::open(Some(path.clone()));
+        assert_eq!(cache.get("d"), Some(&Outcome::Output));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn keys_follow_content_and_profile() {
+        let mut parameters = ProfileData::new();
+        let key = content_key(b"image", &parameters);
+        assert_eq!(key, content_key(b"image", &parameters));
+        assert_ne!(key, content_key(b"other", &parameters));
+
+        parameters.name = "Renamed".to_string();
+        assert_eq!(key, content_key(b"image", &parameters));
+        parameters.jpeg_quality = 50;
+        assert_ne!(key, content_key(b"image", &parameters));
+
+        assert_ne!(path_key(&key, "a.png"), path_key(&key, "b.png"));
+    }
+}
@@ -1,9 +1,16 @@
-use crate::compress::{ImageType, gather_image_paths, process_path};
+use crate::compress::{ImageType, gather_image_paths};
 use crate::errors::AlicErrorType;
-use crate::settings::{self, ProfileData, SettingsData};
+use crate::queue::Queue;
+use crate::report::{self, ReportFormat};
+use crate::settings::{
+    self, ChromaSubsampling, CollisionPolicy, ColorProfilePolicy, ConvertMode, MetadataPolicy,
+    NotSmallerPolicy, ProfileData, SavingsUnit, SettingsData,
+};
+use crate::template::Template;
 use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::path::Path;
 use std::sync::mpsc;
-use std::thread;
 use tauri_plugin_cli::Matches;
 
 /// Check whether the CLI plugin received any `--input` arguments.
@@ -34,6 +41,7 @@ fn run_cli(
     let profile_selector = get_string(matches, "profile");
     let base_profile = resolve_profile(&settings, profile_selector.as_deref())?;
     let profile = apply_overrides(base_profile, matches)?;
+    settings::validate_profile(&profile)?;
 
     let thread_count = match get_string(matches, "threads") {
         Some(v) => {
@@ -50,53 +58,62 @@ fn run_cli(
 
     let recursive = get_flag_pair(matches, "recursive", "no-recursive").unwrap_or(true);
 
+    // Checked up front so a typo doesn't waste a whole run
+    let report_path = get_string(matches, "report");
+    if let Some(path) = &report_path {
+        ReportFormat::from_path(path)?;
+    }
+
+    // Pair each file with the folder it was found under, so an output
+    // folder can mirror the input tree
     let mut paths = Vec::new();
     for input in &inputs {
+        let root = Path::new(input).is_dir().then(|| input.clone());
         let discovered = gather_image_paths(input, recursive);
-        paths.extend(discovered);
+        paths.extend(discovered.into_iter().map(|path| (path, root.clone())));
     }
     dedupe_paths(&mut paths);
     if paths.is_empty() {
         return Err("No supported image files found in --input paths".to_string());
     }
 
+    // Workers pull files off a shared queue as they free up, and each
+    // result is reported the moment it's done
     let (tx, rx) = mpsc::channel();
-
-    let mut panicked = 0_u32;
-    for chunk in paths.chunks(thread_count) {
-        let handles: Vec<_> = chunk
-            .iter()
-            .map(|path| {
-                let tx = tx.clone();
-                let profile = profile.clone();
-                let path = path.clone();
-                let parallel = thread_count as i32;
-                thread::spawn(move || {
-                    let result = process_path(profile, path.clone(), parallel);
-                    tx.send((path, result)).unwrap();
-                })
-            })
-            .collect();
-        for handle in handles {
-            if handle.join().is_err() {
-                eprintln!("err\t<unknown>\tworker thread panicked");
-                panicked += 1;
-            }
-        }
+    let queue = Queue::new(
+        None,
+        thread_count,
+        None,
+        Box::new(move |finished| {
+            let _ = tx.send((
+                finished.file.path.clone(),
+                finished.result.clone(),
+                report::rows(&finished),
+            ));
+        }),
+    );
+    for (path, root) in &paths {
+        queue.add(path.clone(), root.clone(), profile.clone());
     }
-    drop(tx);
 
     let mut ok = 0_u32;
     let mut already_smaller = 0_u32;
-    let mut errors = panicked;
-    for (path, result) in rx {
+    let mut errors = 0_u32;
+    let mut rows = Vec::new();
+    for (path, result, file_rows) in rx.iter().take(paths.len()) {
+        rows.extend(file_rows);
         match result {
             Ok(result) => {
                 ok += 1;
-                println!("ok\t{}\t{}", result.path, result.out_path);
+                for output in &result.outputs {
+                    println!("ok\t{}\t{}", result.path, output.path);
+                }
+                if let Some(snippet) = &result.snippet {
+                    println!("html\t{}\t{}", result.path, snippet.replace('\n', ""));
+                }
             }
             Err(err) => match err.error_type {
-                AlicErrorType::NotSmaller => {
+                AlicErrorType::NotSmaller | AlicErrorType::Skipped => {
                     already_smaller += 1;
                     println!("skip\t{}\t{}", path, err.error);
                 }
@@ -109,15 +126,18 @@ fn run_cli(
     }
 
     println!("summary\tok={ok}\tskipped={already_smaller}\terrors={errors}");
+    if let Some(path) = &report_path {
+        report::write(path, &rows)?;
+    }
     if errors > 0 {
         return Err("One or more files failed".to_string());
     }
     Ok(())
 }
 
-fn dedupe_paths(paths: &mut Vec<String>) {
+fn dedupe_paths(paths: &mut Vec<(String, Option<String>)>) {
     let mut seen = HashSet::new();
-    paths.retain(|p| seen.insert(p.clone()));
+    paths.retain(|(p, _)| seen.insert(p.clone()));
 }
 
 // --- Arg helpers ---
@@ -187,6 +207,58 @@ fn parse_quality(value: &str, key: &str) -> Result<u32, String> {
     Ok(parsed)
 }
 
+fn parse_number(value: &str, key: &str, range: RangeInclusive<u32>) -> Result<u32, String> {
+    let error = || {
+        let (min, max) = (range.start(), range.end());
+        format!("--{key} expects a number between {min} and {max}")
+    };
+    let parsed = value.parse::<u32>().map_err(|_| error())?;
+    if !range.contains(&parsed) {
+        return Err(error());
+    }
+    Ok(parsed)
+}
+
+fn parse_dithering(value: &str) -> Result<f64, String> {
+    let parsed = value
+        .parse::<f64>()
+        .map_err(|_| "--png-dithering expects a number between 0 and 1".to_string())?;
+    if !(0.0..=1.0).contains(&parsed) {
+        return Err("--png-dithering expects a number between 0 and 1".to_string());
+    }
+    Ok(parsed)
+}
+
+fn parse_chroma_subsampling(value: &str) -> Result<ChromaSubsampling, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "auto" => Ok(ChromaSubsampling::Auto),
+        "444" | "4:4:4" => Ok(ChromaSubsampling::CS444),
+        "422" | "4:2:2" => Ok(ChromaSubsampling::CS422),
+        "420" | "4:2:0" => Ok(ChromaSubsampling::CS420),
+        _ => Err("--jpeg-subsampling expects auto, 444, 422 or 420".to_string()),
+    }
+}
+
+fn parse_target_size(value: &str) -> Result<u32, String> {
+    let parsed = value
+        .parse::<u32>()
+        .map_err(|_| "--target-size expects a size in KB".to_string())?;
+    if parsed == 0 {
+        return Err("--target-size must be greater than 0".to_string());
+    }
+    Ok(parsed)
+}
+
+fn parse_similarity(value: &str) -> Result<f64, String> {
+    let parsed = value
+        .parse::<f64>()
+        .map_err(|_| "--target-similarity expects a number between 0 and 1".to_string())?;
+    if !(parsed > 0.0 && parsed <= 1.0) {
+        return Err("--target-similarity expects a number between 0 and 1".to_string());
+    }
+    Ok(parsed)
+}
+
 fn parse_resize(value: &str) -> Result<(u32, u32), String> {
     let (w, h) = value
         .split_once('x')
@@ -203,6 +275,20 @@ fn parse_resize(value: &str) -> Result<(u32, u32), String> {
     Ok((width, height))
 }
 
+fn parse_widths(value: &str) -> Result<Vec<u32>, String> {
+    let widths = value
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| {
+            "--responsive expects comma separated widths, e.g. 480,960,1920".to_string()
+        })?;
+    if widths.is_empty() || widths.contains(&0) {
+        return Err("--responsive widths must be greater than 0".to_string());
+    }
+    Ok(widths)
+}
+
 fn parse_image_type(value: &str) -> Result<ImageType, String> {
     match value.to_ascii_lowercase().as_str() {
         "jpeg" | "jpg" => Ok(ImageType::JPEG),
@@ -211,10 +297,88 @@ fn parse_image_type(value: &str) -> Result<ImageType, String> {
         "gif" => Ok(ImageType::GIF),
         "tiff" | "tif" => Ok(ImageType::TIFF),
         "avif" => Ok(ImageType::AVIF),
+        "jxl" => Ok(ImageType::JXL),
         _ => Err("Unsupported --reformat value".to_string()),
     }
 }
 
+fn parse_image_types(value: &str, key: &str) -> Result<Vec<ImageType>, String> {
+    let types = value
+        .split(',')
+        .map(|v| {
+            parse_image_type(v.trim()).map_err(|_| format!("Unsupported format in --{key}: {v}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if types.is_empty() {
+        return Err(format!("--{key} expects a comma separated list of formats"));
+    }
+    Ok(types)
+}
+
+fn parse_collision_policy(value: &str) -> Result<CollisionPolicy, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "replace" => Ok(CollisionPolicy::Replace),
+        "skip" => Ok(CollisionPolicy::Skip),
+        "rename" => Ok(CollisionPolicy::Rename),
+        "error" => Ok(CollisionPolicy::Error),
+        _ => Err("--on-collision expects replace, skip, rename or error".to_string()),
+    }
+}
+
+/// `5%` is a percentage of the original's size, `2048` a number of bytes.
+fn parse_min_savings(value: &str) -> Result<(u32, SavingsUnit), String> {
+    let error = || "--min-savings expects a percentage (e.g. 5%) or a number of bytes".to_string();
+    match value.trim().strip_suffix('%') {
+        Some(percent) => match percent.trim().parse::<u32>() {
+            Ok(percent) if percent <= 100 => Ok((percent, SavingsUnit::Percent)),
+            _ => Err(error()),
+        },
+        None => value
+            .trim()
+            .parse::<u32>()
+            .map(|bytes| (bytes, SavingsUnit::Bytes))
+            .map_err(|_| error()),
+    }
+}
+
+fn parse_not_smaller(value: &str) -> Result<NotSmallerPolicy, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "skip" => Ok(NotSmallerPolicy::Skip),
+        "copy" => Ok(NotSmallerPolicy::CopyOriginal),
+        "write" => Ok(NotSmallerPolicy::Write),
+        _ => Err("--not-smaller expects skip, copy or write".to_string()),
+    }
+}
+
+/// Turn off each comma separated kind of metadata in `policy`.
+fn parse_strip_metadata(value: &str, policy: &mut MetadataPolicy) -> Result<(), String> {
+    for kind in value.split(',') {
+        let kept = match kind.trim().to_ascii_lowercase().as_str() {
+            "gps" => &mut policy.gps,
+            "camera" => &mut policy.camera,
+            "copyright" => &mut policy.copyright,
+            "thumbnail" => &mut policy.thumbnail,
+            "orientation" => &mut policy.orientation,
+            _ => {
+                return Err(format!(
+                    "Unsupported kind in --strip-metadata: {kind}. Use gps, camera, copyright, thumbnail or orientation"
+                ));
+            }
+        };
+        *kept = false;
+    }
+    Ok(())
+}
+
+fn parse_color_profile(value: &str) -> Result<ColorProfilePolicy, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "keep" => Ok(ColorProfilePolicy::Keep),
+        "srgb" => Ok(ColorProfilePolicy::ConvertToSrgb),
+        "strip" => Ok(ColorProfilePolicy::Strip),
+        _ => Err("--color-profile expects keep, srgb or strip".to_string()),
+    }
+}
+
 fn validate_hex_color(value: &str) -> Result<(), String> {
     if value.len() != 7 || !value.starts_with('#') {
         return Err("--background-fill expects #RRGGBB".to_string());
@@ -249,20 +413,68 @@ fn apply_overrides(mut profile: ProfileData, matches: &Matches) -> Result<Profil
     }
     if let Some(v) = get_string(matches, "reformat") {
         profile.should_convert = true;
+        profile.convert_mode = ConvertMode::Single;
         profile.convert_extension = parse_image_type(&v)?;
     }
+    if let Some(v) = get_string(matches, "reformat-smallest") {
+        profile.should_convert = true;
+        profile.convert_mode = ConvertMode::Smallest;
+        profile.convert_formats = parse_image_types(&v, "reformat-smallest")?;
+    }
+    if let Some(v) = get_string(matches, "reformat-all") {
+        profile.should_convert = true;
+        profile.convert_mode = ConvertMode::All;
+        profile.convert_formats = parse_image_types(&v, "reformat-all")?;
+    }
     if let Some(v) = get_string(matches, "resize") {
         let (w, h) = parse_resize(&v)?;
         profile.should_resize = true;
         profile.resize_width = w;
         profile.resize_height = h;
     }
+    if let Some(v) = get_string(matches, "responsive") {
+        profile.responsive_widths = parse_widths(&v)?;
+        profile.should_responsive = true;
+    } else if has_flag(matches, "no-responsive") {
+        profile.should_responsive = false;
+    }
+    if let Some(v) = get_string(matches, "output-dir") {
+        profile.output_dir = v;
+        profile.should_output_dir = true;
+    } else if has_flag(matches, "no-output-dir") {
+        profile.should_output_dir = false;
+    }
+    if let Some(v) = get_flag_pair(matches, "flatten", "no-flatten") {
+        profile.flatten_output = v;
+    }
+    if let Some(v) = get_string(matches, "on-collision") {
+        profile.on_collision = parse_collision_policy(&v)?;
+    }
+    if let Some(v) = get_flag_pair(matches, "cache", "no-cache") {
+        profile.should_cache = v;
+    }
+    if let Some(v) = get_string(matches, "min-savings") {
+        (profile.min_savings, profile.min_savings_unit) = parse_min_savings(&v)?;
+    }
+    if let Some(v) = get_string(matches, "not-smaller") {
+        profile.not_smaller = parse_not_smaller(&v)?;
+    }
+    if let Some(v) = get_flag_pair(matches, "mark-outputs", "no-mark-outputs") {
+        profile.should_mark_outputs = v;
+    }
     if let Some(v) = get_flag_pair(matches, "add-postfix", "no-postfix") {
         profile.add_postfix = v;
     }
     if let Some(v) = get_string(matches, "postfix") {
         profile.postfix = v;
     }
+    if let Some(v) = get_string(matches, "template") {
+        Template::parse(&v).map_err(|e| format!("--template: {e}"))?;
+        profile.filename_template = v;
+        profile.should_template = true;
+    } else if has_flag(matches, "no-template") {
+        profile.should_template = false;
+    }
     if let Some(v) = get_flag_pair(matches, "lossy", "no-lossy") {
         profile.enable_lossy = v;
     }
@@ -270,7 +482,16 @@ fn apply_overrides(mut profile: ProfileData, matches: &Matches) -> Result<Profil
         profile.keep_timestamps = v;
     }
     if let Some(v) = get_flag_pair(matches, "keep-metadata", "no-keep-metadata") {
-        profile.keep_metadata = v;
+        (profile.metadata, profile.color_profile) = match v {
+            true => (MetadataPolicy::all(), ColorProfilePolicy::Keep),
+            false => (MetadataPolicy::none(), ColorProfilePolicy::Strip),
+        };
+    }
+    if let Some(v) = get_string(matches, "strip-metadata") {
+        parse_strip_metadata(&v, &mut profile.metadata)?;
+    }
+    if let Some(v) = get_string(matches, "color-profile") {
+        profile.color_profile = parse_color_profile(&v)?;
     }
     if let Some(v) = get_string(matches, "background-fill") {
         validate_hex_color(&v)?;
@@ -294,6 +515,61 @@ fn apply_overrides(mut profile: ProfileData, matches: &Matches) -> Result<Profil
     if let Some(v) = get_string(matches, "avif-quality") {
         profile.avif_quality = parse_quality(&v, "avif-quality")?;
     }
+    if let Some(v) = get_string(matches, "jxl-quality") {
+        profile.jxl_quality = parse_quality(&v, "jxl-quality")?;
+    }
+    if let Some(v) = get_flag_pair(matches, "jxl-lossless-jpeg", "no-jxl-lossless-jpeg") {
+        profile.jxl_lossless_jpeg = v;
+    }
+    if let Some(v) = get_flag_pair(matches, "jpeg-progressive", "no-jpeg-progressive") {
+        profile.jpeg_progressive = v;
+    }
+    if let Some(v) = get_string(matches, "jpeg-subsampling") {
+        profile.jpeg_chroma_subsampling = parse_chroma_subsampling(&v)?;
+    }
+    if let Some(v) = get_string(matches, "png-optimization") {
+        profile.png_optimization_level = parse_number(&v, "png-optimization", 0..=6)? as u8;
+    }
+    if let Some(v) = get_string(matches, "png-colors") {
+        profile.png_colors = parse_number(&v, "png-colors", 2..=256)?;
+    }
+    if let Some(v) = get_string(matches, "png-dithering") {
+        profile.png_dithering = parse_dithering(&v)?;
+    }
+    if let Some(v) = get_string(matches, "webp-method") {
+        profile.webp_method = parse_number(&v, "webp-method", 0..=6)?;
+    }
+    if let Some(v) = get_string(matches, "webp-near-lossless") {
+        profile.webp_near_lossless = parse_number(&v, "webp-near-lossless", 0..=100)?;
+    }
+    if let Some(v) = get_string(matches, "avif-speed") {
+        profile.avif_speed = parse_number(&v, "avif-speed", 1..=10)? as u8;
+    }
+    if let Some(v) = get_string(matches, "avif-alpha-quality") {
+        profile.avif_alpha_quality = parse_quality(&v, "avif-alpha-quality")?;
+        profile.should_avif_alpha_quality = true;
+    } else if has_flag(matches, "no-avif-alpha-quality") {
+        profile.should_avif_alpha_quality = false;
+    }
+    if let Some(v) = get_string(matches, "target-size") {
+        profile.target_size_kb = parse_target_size(&v)?;
+        profile.should_target_size = true;
+        // One target given alone replaces the profile's other one
+        if get_string(matches, "target-similarity").is_none() {
+            profile.should_target_similarity = false;
+        }
+    } else if has_flag(matches, "no-target-size") {
+        profile.should_target_size = false;
+    }
+    if let Some(v) = get_string(matches, "target-similarity") {
+        profile.target_similarity = parse_similarity(&v)?;
+        profile.should_target_similarity = true;
+        if get_string(matches, "target-size").is_none() {
+            profile.should_target_size = false;
+        }
+    } else if has_flag(matches, "no-target-similarity") {
+        profile.should_target_similarity = false;
+    }
     Ok(profile)
 }
 
@@ -306,15 +582,39 @@ pub fn print_help() {
     println!("  --input <path>              Input file or directory (required, repeatable)");
     println!("  --profile <name-or-id>      Profile to use");
     println!("  --threads <n>               Images to process concurrently (default: 1)");
+    println!("  --report <path>             Write a report of the run (.csv or .json)");
     println!("  --recursive / --no-recursive");
     println!("                              Recurse into directories (default: recursive)");
     println!("  --resize <WIDTHxHEIGHT>     Resize images");
-    println!("  --reformat <format>          Convert (jpeg|png|webp|gif|tiff|avif)");
+    println!("  --responsive <widths> / --no-responsive");
+    println!("                              Write one file per width (e.g. 480,960,1920)");
+    println!("  --reformat <format>          Convert (jpeg|png|webp|gif|tiff|avif|jxl)");
+    println!("  --reformat-smallest <formats>");
+    println!("                              Convert to each (e.g. webp,avif,jpeg), keep the smallest");
+    println!("  --reformat-all <formats>    Write one output per format (e.g. avif,webp,jpeg)");
     println!("  --overwrite / --no-overwrite");
+    println!("  --output-dir <path> / --no-output-dir");
+    println!("                              Write outputs under this folder, mirroring inputs");
+    println!("  --flatten / --no-flatten     Put all outputs directly in --output-dir");
+    println!("  --on-collision <policy>      When an output exists: replace|skip|rename|error");
+    println!("  --cache / --no-cache         Skip images already processed with these settings");
+    println!("  --min-savings <5%|bytes>     Smallest saving worth writing (default 5%)");
+    println!("  --not-smaller <policy>       Below the saving: skip|copy (the original)|write");
+    println!("  --mark-outputs / --no-mark-outputs");
+    println!("                              Tag PNG/JPEG outputs so they aren't recompressed");
     println!("  --postfix <text>             Postfix text for output filenames");
     println!("  --add-postfix / --no-postfix");
+    println!("  --template <template> / --no-template");
+    println!("                              Name outputs from a template, e.g.");
+    println!("                              {{name}}_{{width}}x{{height}}_q{{quality}}.{{ext}}");
+    println!("                              Tokens: name ext profile width height quality hash8");
+    println!("                              date[:%Y-%m-%d] taken[:%Y-%m-%d] (EXIF capture date)");
     println!("  --lossy / --no-lossy");
     println!("  --keep-metadata / --no-keep-metadata");
+    println!("                              Keep or strip every kind of metadata");
+    println!("  --strip-metadata <kinds>     Strip some kinds of metadata, e.g. gps,thumbnail");
+    println!("                              Kinds: gps camera copyright thumbnail orientation");
+    println!("  --color-profile <policy>     ICC profile: keep|srgb|strip (srgb converts colors)");
     println!("  --keep-timestamps / --no-keep-timestamps");
     println!("  --background-fill <#RRGGBB> / --no-background-fill");
     println!("  --jpeg-quality <1-100>");
@@ -322,6 +622,25 @@ pub fn print_help() {
     println!("  --webp-quality <1-100>");
     println!("  --gif-quality <1-100>");
     println!("  --avif-quality <1-100>");
+    println!("  --jxl-quality <1-100>");
+    println!("  --jxl-lossless-jpeg / --no-jxl-lossless-jpeg");
+    println!("                              Repack JPEGs into JXL so the original can be restored");
+    println!("                              bit for bit (resized and HEIC images are re-encoded)");
+    println!("  --jpeg-progressive / --no-jpeg-progressive");
+    println!("  --jpeg-subsampling <mode>    Chroma subsampling: auto|444|422|420");
+    println!("  --png-optimization <0-6>     Lossless PNG optimization level");
+    println!("  --png-colors <2-256>         Palette size for lossy PNG");
+    println!("  --png-dithering <0-1>        Dithering for lossy PNG");
+    println!("  --webp-method <0-6>          WebP effort (0 fastest, 6 smallest)");
+    println!("  --webp-near-lossless <0-100> Near-lossless for lossless WebP (100 is off)");
+    println!("  --avif-speed <1-10>          AVIF speed (1 slowest/smallest, 10 fastest)");
+    println!("  --avif-alpha-quality <1-100> / --no-avif-alpha-quality");
+    println!("                              Quality for AVIF transparency (default: --avif-quality)");
+    println!("  --target-size <KB> / --no-target-size");
+    println!("                              Pick the highest quality that fits in KB");
+    println!("  --target-similarity <0-1> / --no-target-similarity");
+    println!("                              Pick the lowest quality that keeps this SSIM");
+    println!("                              (not with --target-size)");
     println!("  --help                       Show this help");
     println!("  --version                    Show version");
     println!();
@@ -345,11 +664,95 @@ mod tests {
         assert!(parse_resize("0x100").is_err());
     }
 
+    #[test]
+    fn parse_widths_valid() {
+        assert_eq!(parse_widths("480, 960,1920").unwrap(), vec![480, 960, 1920]);
+        assert!(parse_widths("480,0").is_err());
+        assert!(parse_widths("wide").is_err());
+    }
+
     #[test]
     fn parse_image_type_valid() {
         assert_eq!(parse_image_type("jpg").unwrap(), ImageType::JPEG);
         assert_eq!(parse_image_type("WEBP").unwrap(), ImageType::WEBP);
         assert_eq!(parse_image_type("avif").unwrap(), ImageType::AVIF);
+        assert_eq!(parse_image_type("JXL").unwrap(), ImageType::JXL);
+    }
+
+    #[test]
+    fn parse_target_size_valid() {
+        assert_eq!(parse_target_size("500").unwrap(), 500);
+        assert!(parse_target_size("0").is_err());
+        assert!(parse_target_size("big").is_err());
+    }
+
+    #[test]
+    fn parse_similarity_valid() {
+        assert_eq!(parse_similarity("0.98").unwrap(), 0.98);
+        assert!(parse_similarity("0").is_err());
+        assert!(parse_similarity("1.5").is_err());
+        assert!(parse_similarity("NaN").is_err());
+    }
+
+    #[test]
+    fn parse_image_types_valid() {
+        assert_eq!(
+            parse_image_types("webp, avif,JPG", "test").unwrap(),
+            vec![ImageType::WEBP, ImageType::AVIF, ImageType::JPEG]
+        );
+        assert!(parse_image_types("webp,bmp", "test").is_err());
+    }
+
+    #[test]
+    fn parse_collision_policy_valid() {
+        assert_eq!(parse_collision_policy("Rename").unwrap(), CollisionPolicy::Rename);
+        assert!(parse_collision_policy("ask").is_err());
+    }
+
+    #[test]
+    fn parse_min_savings_valid() {
+        assert_eq!(parse_min_savings("10%").unwrap(), (10, SavingsUnit::Percent));
+        assert_eq!(parse_min_savings("2048").unwrap(), (2048, SavingsUnit::Bytes));
+        assert!(parse_min_savings("101%").is_err());
+        assert!(parse_min_savings("2kb").is_err());
+        assert_eq!(parse_not_smaller("copy").unwrap(), NotSmallerPolicy::CopyOriginal);
+        assert!(parse_not_smaller("keep").is_err());
+    }
+
+    #[test]
+    fn parse_encoder_settings_valid() {
+        assert_eq!(parse_number("6", "webp-method", 0..=6).unwrap(), 6);
+        assert!(parse_number("7", "webp-method", 0..=6).is_err());
+        assert!(parse_number("-1", "webp-method", 0..=6).is_err());
+        assert_eq!(parse_dithering("0.5").unwrap(), 0.5);
+        assert!(parse_dithering("2").is_err());
+        assert_eq!(parse_chroma_subsampling("4:2:0").unwrap(), ChromaSubsampling::CS420);
+        assert!(parse_chroma_subsampling("411").is_err());
+    }
+
+    #[test]
+    fn parse_strip_metadata_valid() {
+        let mut policy = MetadataPolicy::all();
+        parse_strip_metadata("gps, Thumbnail", &mut policy).unwrap();
+        assert_eq!(
+            policy,
+            MetadataPolicy {
+                gps: false,
+                thumbnail: false,
+                ..MetadataPolicy::all()
+            }
+        );
+        assert!(parse_strip_metadata("location", &mut policy).is_err());
+        assert!(parse_strip_metadata("icc", &mut policy).is_err());
+    }
+
+    #[test]
+    fn parse_color_profile_valid() {
+        assert_eq!(
+            parse_color_profile("sRGB").unwrap(),
+            ColorProfilePolicy::ConvertToSrgb
+        );
+        assert!(parse_color_profile("p3").is_err());
     }
 
     #[test]
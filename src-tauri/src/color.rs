@@ -0,0 +1,139 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use log::debug;
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+
+use crate::animation;
+use crate::errors::{AlicError, AlicErrorType};
+use crate::resize;
+
+/// Convert `data` from the `icc` profile to sRGB and encode it back to its
+/// own format, losslessly where the format allows. Profiles that can't be
+/// read, aren't RGB or already use the sRGB primaries leave `data` as it is.
+pub fn to_srgb(data: Vec<u8>, icc: &[u8], gif_quality: u32) -> Result<Vec<u8>, AlicError> {
+    let profile = match ColorProfile::new_from_slice(icc) {
+        Ok(profile) if profile.color_space == DataColorSpace::Rgb => profile,
+        Ok(profile) => {
+            debug!("Not converting {:?} colors to sRGB", profile.color_space);
+            return Ok(data);
+        }
+        Err(e) => {
+            debug!("Could not read ICC profile: {e:?}");
+            return Ok(data);
+        }
+    };
+    if is_srgb(&profile) {
+        return Ok(data);
+    }
+    let transform = profile
+        .create_transform_8bit(
+            Layout::Rgba,
+            &ColorProfile::new_srgb(),
+            Layout::Rgba,
+            TransformOptions::default(),
+        )
+        .map_err(color_error)?;
+    let convert = |image: &mut RgbaImage| {
+        let source = image.as_raw().clone();
+        transform.transform(&source, image).map_err(color_error)
+    };
+
+    if animation::is_animated(&data) {
+        let mut animation = animation::decode(&data)?;
+        for frame in &mut animation.frames {
+            convert(frame.buffer_mut())?;
+        }
+        return animation::encode(&animation, animation.format, gif_quality);
+    }
+
+    let (image, format) = resize::read_image(&data)?;
+    let has_alpha = image.color().has_alpha();
+    let mut rgba = image.into_rgba8();
+    convert(&mut rgba)?;
+    let image = match has_alpha {
+        true => DynamicImage::ImageRgba8(rgba),
+        false => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).into_rgb8()),
+    };
+    let mut out = Vec::new();
+    match format {
+        // At full quality, as the output is encoded again anyway
+        ImageFormat::Jpeg => image.write_with_encoder(JpegEncoder::new_with_quality(&mut out, 100)),
+        format => image.write_to(&mut Cursor::new(&mut out), format),
+    }
+    .map_err(color_error)?;
+    Ok(out)
+}
+
+/// Whether `profile` has the sRGB primaries. Tone curves aren't compared,
+/// as profiles with those primaries nearly always use the sRGB curve.
+fn is_srgb(profile: &ColorProfile) -> bool {
+    let srgb = ColorProfile::new_srgb();
+    [
+        (profile.red_colorant, srgb.red_colorant),
+        (profile.green_colorant, srgb.green_colorant),
+        (profile.blue_colorant, srgb.blue_colorant),
+    ]
+    .iter()
+    .all(|(a, b)| (a.x - b.x).abs() < 0.01 && (a.y - b.y).abs() < 0.01 && (a.z - b.z).abs() < 0.01)
+}
+
+fn color_error(e: impl std::fmt::Debug) -> AlicError {
+    AlicError {
+        error: format!("Error converting colors to sRGB: {e:?}"),
+        error_type: AlicErrorType::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn png(color: [u8; 3]) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(2, 2, Rgb(color))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn pixel(data: &[u8]) -> [u8; 3] {
+        image::load_from_memory(data)
+            .unwrap()
+            .to_rgb8()
+            .get_pixel(0, 0)
+            .0
+    }
+
+    #[test]
+    fn converts_wide_gamut_to_srgb() {
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        let converted = to_srgb(png([60, 180, 60]), &p3, 80)
+            .map_err(|e| e.error)
+            .unwrap();
+        // The same green is more saturated in sRGB numbers
+        let [r, g, b] = pixel(&converted);
+        assert!(r < 60 && g > 180 && b < 60, "{:?}", [r, g, b]);
+
+        // Grays look the same in both
+        let converted = to_srgb(png([128, 128, 128]), &p3, 80)
+            .map_err(|e| e.error)
+            .unwrap();
+        let [r, g, b] = pixel(&converted);
+        assert!(r.abs_diff(128) <= 1 && g == r && b == r);
+    }
+
+    #[test]
+    fn leaves_srgb_alone() {
+        let srgb = ColorProfile::new_srgb().encode().unwrap();
+        let data = png([60, 180, 60]);
+        assert_eq!(
+            to_srgb(data.clone(), &srgb, 80)
+                .map_err(|e| e.error)
+                .unwrap(),
+            data
+        );
+    }
+}
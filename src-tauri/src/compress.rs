@@ -1,9 +1,20 @@
 use super::settings;
+use crate::animation;
+use crate::cache;
+use crate::color;
 use crate::errors::{AlicError, AlicErrorType};
-use crate::events::{AddFileEvent, ErrorEvent};
+use crate::events::{AddFileEvent, ErrorEvent, JobStage};
+use crate::history;
+use crate::jobs::Job;
 use crate::macos;
+use crate::marker;
+use crate::metadata::Metadata;
+use crate::report;
 use crate::resize;
-use caesium::parameters::CSParameters;
+use crate::ssim;
+use crate::template::{Encoded, Template, TemplateValues};
+use caesium::parameters::{CSParameters, ChromaSubsampling};
+use chrono::{DateTime, Local, NaiveDateTime};
 use image::ImageFormat;
 use image::{self};
 use log::debug;
@@ -15,7 +26,15 @@ use std::os::unix::fs::MetadataExt;
 use std::time::SystemTime;
 use tauri_specta::Event;
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// Output paths written in the current batch and the input each came from,
+/// so inputs that resolve to the same output (`a.png` and `a.jpg` both
+/// converting to `a.min.webp`) don't silently replace each other.
+static BATCH_OUTPUTS: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(Default::default);
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize, Type)]
 #[serde(rename_all = "camelCase")]
@@ -28,6 +47,7 @@ pub struct FileEntry {
     pub ext: Option<String>,
     pub savings: Option<u32>,
     pub error: Option<String>,
+    pub root: Option<String>,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, serde::Serialize, serde::Deserialize, Type)]
@@ -56,6 +76,9 @@ pub enum ImageType {
     GIF,
     TIFF,
     AVIF,
+    JXL,
+    // Input only, decoded by ImageIO
+    HEIC,
 }
 
 impl ImageType {
@@ -67,6 +90,9 @@ impl ImageType {
             ImageType::GIF => ImageFormat::Gif.extensions_str(),
             ImageType::TIFF => ImageFormat::Tiff.extensions_str(),
             ImageType::AVIF => ImageFormat::Avif.extensions_str(),
+            // The image crate doesn't know JPEG XL
+            ImageType::JXL => &["jxl"],
+            ImageType::HEIC => &["heic", "heif"],
         }
     }
     pub fn to_casium_type(&self) -> Option<caesium::SupportedFileTypes> {
@@ -77,6 +103,8 @@ impl ImageType {
             ImageType::GIF => Some(caesium::SupportedFileTypes::Gif),
             ImageType::TIFF => Some(caesium::SupportedFileTypes::Tiff),
             ImageType::AVIF => None, // AVIF uses ravif, not libcaesium
+            ImageType::JXL => None,  // JXL uses libjxl, not libcaesium
+            ImageType::HEIC => None, // HEIC is decoded by ImageIO and never written
         }
     }
     pub fn preferred_extension(&self) -> &str {
@@ -87,17 +115,66 @@ impl ImageType {
             ImageType::GIF => "gif",
             ImageType::TIFF => "tiff",
             ImageType::AVIF => "avif",
+            ImageType::JXL => "jxl",
+            ImageType::HEIC => "heic",
+        }
+    }
+    pub fn mime_type(&self) -> &str {
+        match self {
+            ImageType::JPEG => ImageFormat::Jpeg.to_mime_type(),
+            ImageType::PNG => ImageFormat::Png.to_mime_type(),
+            ImageType::WEBP => ImageFormat::WebP.to_mime_type(),
+            ImageType::GIF => ImageFormat::Gif.to_mime_type(),
+            ImageType::TIFF => ImageFormat::Tiff.to_mime_type(),
+            ImageType::AVIF => ImageFormat::Avif.to_mime_type(),
+            ImageType::JXL => "image/jxl",
+            ImageType::HEIC => "image/heic",
         }
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Type)]
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CompressResult {
     pub path: String,
     pub out_size: u32,
     pub out_path: String,
     pub result: String,
+    pub quality: Option<u32>,
+    pub outputs: Vec<OutputFile>,
+    pub snippet: Option<String>,
+    pub original_size: u32,
+    pub original_format: ImageType,
+    pub original_width: Option<u32>,
+    pub original_height: Option<u32>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputFile {
+    pub path: String,
+    pub size: u32,
+    pub format: ImageType,
+    pub quality: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// The prepared source, or one width of a responsive set
+struct Variant {
+    suffix: String,
+    data: Vec<u8>,
+}
+
+/// An encoded output that hasn't been written yet
+struct EncodedOutput {
+    suffix: String,
+    data: Vec<u8>,
+    quality: Option<u32>,
+    format: ImageType,
+    dimensions: Option<(u32, u32)>,
+    // The original, written in place of an output that didn't save enough
+    is_original: bool,
 }
 
 #[derive(Debug)]
@@ -127,20 +204,11 @@ impl ImageData {
     }
 }
 
-#[tauri::command]
-#[specta::specta]
-pub async fn process_img(
-    parameters: settings::ProfileData,
-    file: FileEntry,
-    parallel_images: i32,
-) -> Result<CompressResult, AlicError> {
-    process_img_internal(parameters, file, parallel_images)
-}
-
 pub fn process_path(
     parameters: settings::ProfileData,
     path: String,
-    parallel_images: i32,
+    root: Option<String>,
+    job: &Job,
 ) -> Result<CompressResult, AlicError> {
     let file = FileEntry {
         path,
@@ -151,14 +219,15 @@ pub fn process_path(
         ext: None,
         savings: None,
         error: None,
+        root,
     };
-    process_img_internal(parameters, file, parallel_images)
+    process_img_internal(parameters, file, job)
 }
 
 fn process_img_internal(
-    parameters: settings::ProfileData,
+    mut parameters: settings::ProfileData,
     file: FileEntry,
-    parallel_images: i32,
+    job: &Job,
 ) -> Result<CompressResult, AlicError> {
     // check file exists,
     // get type,
@@ -170,6 +239,7 @@ fn process_img_internal(
     // if not savings, delete temp file, return
     // if out path is same as original, delete original
     // move temp file to out path
+    job.stage(JobStage::Reading);
     let image_data = match read_image_info(&file.path) {
         Ok(img) => img,
         Err(err) => {
@@ -179,15 +249,85 @@ fn process_img_internal(
             });
         }
     };
+    job.check()?;
 
-    let out_path = get_out_path(&parameters, &file.path, &image_data.image_type);
+    let cache_key = match parameters.should_cache {
+        true => Some(cache::content_key(&image_data.data, &parameters)),
+        false => None,
+    };
+    if let Some(key) = &cache_key {
+        check_cache(key, &file.path)?;
+    }
 
-    // String equality misses case-insensitive filesystems (IMG.JPG vs
-    // IMG.jpg is the same file on default APFS), so compare inodes when
-    // something exists at the output path.
-    let replacing_original = is_same_file(&file.path, &out_path);
+    // Recompressing an Alic output in place only loses more quality
+    if parameters.enable_lossy
+        && !parameters.should_convert
+        && !parameters.should_resize
+        && !parameters.should_responsive
+        && marker::has_marker(&image_data.data, &image_data.image_type)
+    {
+        return Err(AlicError {
+            error: "Image was already compressed by Alic.".to_string(),
+            error_type: AlicErrorType::Skipped,
+        });
+    }
 
-    if replacing_original && !parameters.should_overwrite {
+    // Repacking a JPEG into JPEG XL only stays lossless for the original
+    // file. Resized pixels are encoded like any other format instead, and so
+    // are HEIC photos, whose full quality stand-in JPEG would repack far
+    // bigger than the HEIC.
+    if image_data.image_type == ImageType::HEIC {
+        parameters.jxl_lossless_jpeg = false;
+    }
+    let repacks_jpeg = parameters.jxl_lossless_jpeg
+        && image_data.image_type == ImageType::JPEG
+        && target_formats(&parameters, &image_data.image_type).contains(&ImageType::JXL);
+    if repacks_jpeg && (parameters.should_resize || parameters.should_responsive) {
+        debug!("Resizing, so JPEG XL is encoded from pixels instead of repacking the JPEG");
+        parameters.jxl_lossless_jpeg = false;
+    }
+
+    let template = match parameters.should_template {
+        true => Some(
+            Template::parse(&parameters.filename_template).map_err(|error| AlicError {
+                error,
+                error_type: AlicErrorType::Unknown,
+            })?,
+        ),
+        false => None,
+    };
+    let naming = Naming {
+        parameters: &parameters,
+        path: &file.path,
+        root: file.root.as_deref(),
+        guessed_format: &image_data.image_type,
+        template,
+        date: Local::now().naive_local(),
+        taken: resize::capture_date(&image_data.data)
+            .unwrap_or_else(|| DateTime::<Local>::from(image_data.modified).naive_local()),
+    };
+
+    // Formats whose output would land on the original are only candidates
+    // when overwriting is allowed. String equality misses case-insensitive
+    // filesystems (IMG.JPG vs IMG.jpg is the same file on default APFS), so
+    // compare inodes when something exists at the output path.
+    let (candidates, blocked): (Vec<ImageType>, Vec<ImageType>) =
+        target_formats(&parameters, &image_data.image_type)
+            .into_iter()
+            .partition(|format| {
+                // Responsive outputs always carry a width suffix. Templates
+                // naming the encoded output are checked again before writing.
+                parameters.should_overwrite
+                    || parameters.should_responsive
+                    || naming
+                        .out_path(format, "", None)
+                        .is_none_or(|out_path| !is_same_file(&file.path, &out_path))
+            });
+
+    // Writing every format must not silently drop one of them
+    let writes_all =
+        parameters.should_convert && parameters.convert_mode == settings::ConvertMode::All;
+    if candidates.is_empty() || (writes_all && !blocked.is_empty()) {
         return Err(AlicError {
             error:
                 "Image would be overwritten. Enable \"Allow Overwrite\" in settings to allow this."
@@ -196,133 +336,558 @@ fn process_img_internal(
         });
     }
 
-    let should_convert =
-        parameters.should_convert && parameters.convert_extension != image_data.image_type;
-
-    // Determine target format for AVIF handling
-    let target_format = if should_convert {
-        &parameters.convert_extension
-    } else {
-        &image_data.image_type
+    // Nothing past this point reads JPEG XL or HEIC. JPEG XL becomes a
+    // lossless PNG, and HEIC a full quality JPEG that keeps its EXIF, so it
+    // follows the same metadata rules as any other JPEG. Encode as if the
+    // source were the decoded file.
+    let (source_data, source_format) = match image_data.image_type {
+        ImageType::JXL => (
+            decode_jxl(&image_data.data).map_err(|error| AlicError {
+                error,
+                error_type: AlicErrorType::UnsupportedFileType,
+            })?,
+            ImageType::PNG,
+        ),
+        ImageType::HEIC => (
+            macos::heif_to_jpeg(&image_data.data).map_err(|error| AlicError {
+                error,
+                error_type: AlicErrorType::UnsupportedFileType,
+            })?,
+            ImageType::JPEG,
+        ),
+        _ => (image_data.data, image_data.image_type.clone()),
+    };
+    job.check()?;
+    let original_dimensions = resize::image_dimensions(&source_data);
+    // Repacked as it is, so neither the orientation nor the colors change
+    let repacked_jpeg = (repacks_jpeg && parameters.jxl_lossless_jpeg).then(|| source_data.clone());
+
+    // When the orientation flag is kept, every output can carry EXIF and the
+    // pixels aren't resized, the flag is written back and the image stays
+    // upright without re-encoding through the image crate. In every other
+    // case the flag would be lost or would rotate pixels that already were,
+    // so we bake the orientation into the pixels instead.
+    let preserves_orientation_flag = parameters.metadata.orientation
+        && !parameters.should_resize
+        && !parameters.should_responsive
+        && candidates
+            .iter()
+            .all(|format| matches!(format, ImageType::JPEG | ImageType::PNG | ImageType::WEBP));
+    let mut metadata = Metadata::read(
+        &source_data,
+        &parameters.metadata,
+        preserves_orientation_flag,
+    );
+    let converts_to_srgb = match parameters.color_profile {
+        settings::ColorProfilePolicy::Keep => candidates
+            .iter()
+            .any(|format| !carries_icc(format, &parameters, &source_format)),
+        settings::ColorProfilePolicy::ConvertToSrgb => true,
+        settings::ColorProfilePolicy::Strip => false,
     };
 
-    // Handle resize ourselves to get around memory limit issues
-    let cs_params = create_cs_parameters(&parameters);
-
-    // When metadata is kept and we stay in the same EXIF-capable format,
-    // libcaesium preserves the orientation flag, so the image stays upright
-    // without re-encoding through the image crate (which would drop *all*
-    // metadata, not just orientation). In every other case the flag would be
-    // lost — metadata stripped, format converted, or AVIF which can't carry it
-    // — so we bake the orientation into the pixels instead.
-    let preserves_orientation_flag =
-        parameters.keep_metadata && !should_convert && *target_format != ImageType::AVIF;
-
+    job.stage(JobStage::Resizing);
+    let is_animated = animation::is_animated(&source_data);
     let data = match parameters.should_resize {
         true => resize::resize(
-            image_data.data,
+            source_data,
             parameters.resize_width,
             parameters.resize_height,
             parameters.should_background_fill,
             &parameters.background_fill,
-            image_data.image_type == ImageType::GIF,
-        )?,
-        false if preserves_orientation_flag => image_data.data,
-        false => resize::normalize_orientation(
-            image_data.data,
-            image_data.image_type == ImageType::GIF,
+            is_animated,
+            parameters.gif_quality,
         )?,
+        false if preserves_orientation_flag => source_data,
+        false => resize::normalize_orientation(source_data, is_animated)?,
     };
 
-    // AVIF uses ravif directly instead of libcaesium
-    let result = if *target_format == ImageType::AVIF {
-        compress_avif(&data, &parameters, parallel_images)
-    } else if should_convert {
-        convert_image(data, cs_params, parameters.convert_extension.clone())
-    } else {
-        compress_image(data, cs_params, image_data.image_type)
+    // Once the pixels are sRGB, embedding the old profile would shift them
+    // again, so every output goes without it
+    let data = match metadata.icc() {
+        Some(icc) if converts_to_srgb => color::to_srgb(data, icc, parameters.gif_quality)?,
+        _ => data,
+    };
+    if converts_to_srgb || parameters.color_profile == settings::ColorProfilePolicy::Strip {
+        metadata.strip_icc();
+    }
+    job.check()?;
+
+    let variants = match parameters.should_responsive {
+        true => responsive_variants(data, &parameters, is_animated)?,
+        false => vec![Variant {
+            suffix: String::new(),
+            data,
+        }],
     };
 
-    if result.is_err() {
-        return Err(AlicError {
-            error: result.err().unwrap().to_string(),
-            error_type: AlicErrorType::Unknown,
-        });
+    // Encode every candidate. In "all" mode every result is written and any
+    // failure fails the image before anything touches the disk. Otherwise
+    // the smallest is kept, and a candidate that fails (e.g. can't meet the
+    // target size) only fails the image when no other candidate succeeded.
+    let mut candidates = candidates;
+    let mut encoded_outputs: Vec<EncodedOutput> = Vec::new();
+    for (variant_index, variant) in variants.iter().enumerate() {
+        // The image crate can't read AVIF, so take dimensions from the
+        // variant when the output's can't be read
+        let variant_dimensions = resize::image_dimensions(&variant.data);
+        let mut variant_outputs: Vec<(Vec<u8>, Option<u32>, ImageType)> = Vec::new();
+        let mut first_error = None;
+        for (format_index, format) in candidates.iter().enumerate() {
+            job.stage(JobStage::Encoding {
+                output: (variant_index * candidates.len() + format_index + 1) as u32,
+                outputs: (variants.len() * candidates.len()) as u32,
+            });
+            let data = match (&repacked_jpeg, format) {
+                (Some(jpeg), ImageType::JXL) => jpeg,
+                _ => &variant.data,
+            };
+            match encode_target(data, &parameters, &metadata, format, &source_format, job) {
+                Ok((encoded, quality)) => {
+                    debug!("Encoded {format:?} at {} bytes", encoded.len());
+                    variant_outputs.push((encoded, quality, format.clone()));
+                }
+                Err(err) if writes_all || matches!(err.error_type, AlicErrorType::Cancelled) => {
+                    return Err(err);
+                }
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        if !writes_all
+            && let Some(smallest) = variant_outputs
+                .drain(..)
+                .min_by_key(|(encoded, _, _)| encoded.len())
+        {
+            variant_outputs.push(smallest);
+        }
+        if variant_outputs.is_empty() {
+            return Err(first_error.expect("candidates is not empty"));
+        }
+        // A responsive set uses the same formats at every width, so the
+        // largest width (encoded first) decides them for the rest
+        candidates = variant_outputs.iter().map(|(_, _, f)| f.clone()).collect();
+        encoded_outputs.extend(variant_outputs.into_iter().map(|(data, quality, format)| {
+            EncodedOutput {
+                suffix: variant.suffix.clone(),
+                dimensions: resize::image_dimensions(&data).or(variant_dimensions),
+                data: match parameters.should_mark_outputs {
+                    true => marker::add_marker(data, &format),
+                    false => data,
+                },
+                quality,
+                format,
+                is_original: false,
+            }
+        }));
     }
 
-    let compressed_data = result.unwrap();
-    let compressed_size = compressed_data.len() as f64;
-    if !parameters.should_convert && compressed_size > image_data.size as f64 * 0.95 {
+    // Responsive variants are smaller by design, and conversions are worth
+    // having at any size, so only recompressions are held to the minimum
+    if !parameters.should_responsive {
+        let meets_minimum = |output: &EncodedOutput| {
+            output.format != image_data.image_type
+                || saves_enough(output.data.len(), image_data.size, &parameters)
+        };
+        match parameters.not_smaller {
+            settings::NotSmallerPolicy::Skip => encoded_outputs.retain(meets_minimum),
+            settings::NotSmallerPolicy::CopyOriginal
+                if !encoded_outputs.iter().all(meets_minimum) =>
+            {
+                let original = fs::read(&file.path).map_err(|e| AlicError {
+                    error: format!("Could not read {}: {e}", file.path),
+                    error_type: AlicErrorType::FileNotFound,
+                })?;
+                for output in encoded_outputs.iter_mut().filter(|o| !meets_minimum(o)) {
+                    *output = EncodedOutput {
+                        suffix: output.suffix.clone(),
+                        dimensions: resize::image_dimensions(&original),
+                        data: original.clone(),
+                        quality: None,
+                        format: image_data.image_type.clone(),
+                        is_original: true,
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let (encoded_outputs, out_paths): (Vec<EncodedOutput>, Vec<String>) = drop_redundant_copies(
+        encoded_outputs
+            .into_iter()
+            .map(|output| {
+                let encoded = Encoded {
+                    data: &output.data,
+                    width: output.dimensions.map(|(w, _)| w),
+                    height: output.dimensions.map(|(_, h)| h),
+                    quality: output.quality,
+                };
+                let out_path = naming
+                    .out_path(&output.format, &output.suffix, Some(&encoded))
+                    .expect("encoded output is known");
+                (output, out_path)
+            })
+            .collect(),
+        &file.path,
+    )
+    .into_iter()
+    .unzip();
+    if encoded_outputs.is_empty() {
+        if let Some(key) = cache_key {
+            cache::record(key, cache::Outcome::NotSmaller);
+        }
         return Err(AlicError {
             error: "Image cannot be compressed further.".to_string(),
             error_type: AlicErrorType::NotSmaller,
         });
     }
 
-    // Whatever currently sits at the output path (the original on
-    // overwrite, or an unrelated/previous output file) goes to the trash
-    // so it can be recovered.
-    if Path::new(&out_path).exists()
-        && let Err(e) = macos::trash_file(&out_path)
-    {
-        // Trashing can fail on volumes without Trash support (network
-        // shares). Hard-fail only when we would destroy the original;
-        // stale outputs are deleted like previous versions did.
-        if replacing_original {
-            return Err(AlicError {
-                error: format!("Could not move {out_path} to trash: {e}"),
-                error_type: AlicErrorType::Unknown,
-            });
+    if !parameters.should_overwrite && out_paths.iter().any(|p| is_same_file(&file.path, p)) {
+        return Err(AlicError {
+            error:
+                "Image would be overwritten. Enable \"Allow Overwrite\" in settings to allow this."
+                    .to_string(),
+            error_type: AlicErrorType::WontOverwrite,
+        });
+    }
+
+    // The last chance to cancel. Writing isn't interrupted, so a cancelled
+    // job never leaves some of its outputs behind.
+    job.check()?;
+    let out_paths = claim_out_paths(out_paths, &file.path, &parameters.on_collision)?;
+    if out_paths.iter().all(Option::is_none) {
+        return Err(AlicError {
+            error: "Output already exists.".to_string(),
+            error_type: AlicErrorType::Skipped,
+        });
+    }
+
+    job.stage(JobStage::Writing);
+    let mut outputs = Vec::new();
+    for (output, out_path) in encoded_outputs.into_iter().zip(out_paths) {
+        let Some(out_path) = out_path else {
+            continue;
+        };
+        write_output(
+            &out_path,
+            &output.data,
+            &file.path,
+            &parameters,
+            image_data.created,
+            image_data.modified,
+        )?;
+        if cache_key.is_some() && !output.is_original {
+            cache::record(
+                cache::content_key(&output.data, &parameters),
+                cache::Outcome::Output,
+            );
         }
-        if let Err(e) = fs::remove_file(&out_path) {
-            return Err(AlicError {
-                error: format!("Could not replace existing file at {out_path}: {e}"),
-                error_type: AlicErrorType::Unknown,
-            });
+        outputs.push(OutputFile {
+            path: out_path,
+            size: output.data.len() as u32,
+            format: output.format,
+            quality: output.quality,
+            width: output.dimensions.map(|(w, _)| w),
+            height: output.dimensions.map(|(_, h)| h),
+        });
+    }
+
+    if let Some(key) = cache_key {
+        cache::record(
+            cache::path_key(&key, &file.path),
+            cache::Outcome::Compressed {
+                outputs: outputs.iter().map(|output| output.path.clone()).collect(),
+            },
+        );
+    }
+
+    let snippet = match parameters.should_responsive {
+        true => Some(responsive_snippet(&outputs)),
+        false => None,
+    };
+
+    let primary = &outputs[0];
+    Ok(CompressResult {
+        path: file.path,
+        out_size: primary.size,
+        out_path: primary.path.clone(),
+        result: "Success".to_string(),
+        quality: primary.quality,
+        outputs,
+        snippet,
+        original_size: image_data.size as u32,
+        original_format: image_data.image_type,
+        original_width: original_dimensions.map(|(w, _)| w),
+        original_height: original_dimensions.map(|(_, h)| h),
+    })
+}
+
+/// Drop the copies of the original that would land on the original itself,
+/// on an encoded output or on an earlier copy. An encoded output always
+/// wins over a copy, whichever format the copy took its name from.
+fn drop_redundant_copies(
+    outputs: Vec<(EncodedOutput, String)>,
+    source_path: &str,
+) -> Vec<(EncodedOutput, String)> {
+    let encoded_paths: Vec<String> = outputs
+        .iter()
+        .filter(|(output, _)| !output.is_original)
+        .map(|(_, out_path)| out_path.clone())
+        .collect();
+    let mut copied_paths: Vec<String> = Vec::new();
+    outputs
+        .into_iter()
+        .filter(|(output, out_path)| {
+            if !output.is_original {
+                return true;
+            }
+            let is_new = !encoded_paths.contains(out_path) && !copied_paths.contains(out_path);
+            copied_paths.push(out_path.clone());
+            is_new && !is_same_file(source_path, out_path)
+        })
+        .collect()
+}
+
+/// Whether an output of `out_size` bytes saves the profile's minimum over
+/// an original of `original_size` bytes.
+fn saves_enough(out_size: usize, original_size: u64, parameters: &settings::ProfileData) -> bool {
+    let saved = original_size as f64 - out_size as f64;
+    match parameters.min_savings_unit {
+        settings::SavingsUnit::Percent => {
+            saved >= original_size as f64 * f64::from(parameters.min_savings) / 100.0
         }
+        settings::SavingsUnit::Bytes => saved >= f64::from(parameters.min_savings),
     }
-    let mut new_file = match fs::File::create_new(&out_path) {
-        Ok(file) => file,
-        Err(e) => {
+}
+
+/// Fail early with the remembered outcome when this image was already
+/// processed with the same profile settings.
+fn check_cache(content_key: &str, path: &str) -> Result<(), AlicError> {
+    match cache::lookup(content_key) {
+        Some(cache::Outcome::NotSmaller) => {
             return Err(AlicError {
-                error: e.to_string(),
-                error_type: AlicErrorType::Unknown,
+                error: "Image cannot be compressed further.".to_string(),
+                error_type: AlicErrorType::NotSmaller,
             });
         }
-    };
-    let write_result = new_file.write_all(&compressed_data);
-    match write_result {
-        Ok(_) => {}
-        Err(e) => {
+        Some(cache::Outcome::Output) => {
             return Err(AlicError {
-                error: e.to_string(),
-                error_type: AlicErrorType::Unknown,
+                error: "Image is already an output of this profile.".to_string(),
+                error_type: AlicErrorType::Skipped,
             });
         }
+        _ => {}
+    }
+    // Only skip a compressed image while its outputs are still around
+    if let Some(cache::Outcome::Compressed { outputs }) =
+        cache::lookup(&cache::path_key(content_key, path))
+        && let Some(first) = outputs.first()
+        && outputs.iter().all(|output| Path::new(output).exists())
+    {
+        return Err(AlicError {
+            error: format!("Unchanged since it was compressed to {first}."),
+            error_type: AlicErrorType::Skipped,
+        });
+    }
+    Ok(())
+}
+
+/// Apply the profile's collision policy to each output path and claim the
+/// results for `input` in the current batch. `None` means the output is
+/// skipped. Nothing is claimed if any output fails.
+fn claim_out_paths(
+    out_paths: Vec<String>,
+    input: &str,
+    policy: &settings::CollisionPolicy,
+) -> Result<Vec<Option<String>>, AlicError> {
+    let mut batch_outputs = BATCH_OUTPUTS.lock().unwrap();
+    let mut claimed: Vec<Option<String>> = Vec::new();
+    for out_path in out_paths {
+        let mut candidate = out_path.clone();
+        let mut counter = 1;
+        let resolved = loop {
+            let other_input = batch_outputs
+                .get(&candidate)
+                .filter(|owner| owner.as_str() != input)
+                .cloned();
+            // Replacing the original is decided by "Allow Overwrite"
+            let exists = Path::new(&candidate).exists() && !is_same_file(input, &candidate);
+            if other_input.is_none() && !exists {
+                break Some(candidate);
+            }
+            match policy {
+                // Never replace another output from the same batch
+                settings::CollisionPolicy::Replace if other_input.is_none() => {
+                    break Some(candidate);
+                }
+                settings::CollisionPolicy::Skip => break None,
+                settings::CollisionPolicy::Rename => {
+                    counter += 1;
+                    candidate = numbered_path(&out_path, counter);
+                }
+                _ => {
+                    let error = match other_input {
+                        Some(other) => format!("{other} is also written to {candidate}."),
+                        None => format!("{candidate} already exists."),
+                    };
+                    return Err(AlicError {
+                        error,
+                        error_type: AlicErrorType::OutputExists,
+                    });
+                }
+            }
+        };
+        claimed.push(resolved);
+    }
+    for out_path in claimed.iter().flatten() {
+        batch_outputs.insert(out_path.clone(), input.to_string());
+    }
+    Ok(claimed)
+}
+
+/// `a/name.min.webp` becomes `a/name.min (2).webp`
+fn numbered_path(path: &str, counter: u32) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem} ({counter}).{}", extension.to_string_lossy()),
+        None => format!("{stem} ({counter})"),
     };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
 
-    if parameters.keep_timestamps {
-        let times = fs::FileTimes::new()
-            .set_created(image_data.created)
-            .set_modified(image_data.modified);
-        match new_file.set_times(times) {
-            Ok(_) => {}
-            Err(e) => {
+/// Forget the outputs claimed so far, starting a new batch.
+#[tauri::command]
+#[specta::specta]
+pub fn clear_batch() {
+    BATCH_OUTPUTS.lock().unwrap().clear();
+    history::new_batch();
+    report::clear_session();
+}
+
+/// Write `data` to `out_path` so that a crash or full disk never leaves it
+/// missing: the data goes to a synced temporary file next to it, whatever
+/// is already at `out_path` is copied to the trash, and only then is the
+/// temporary file renamed over it.
+fn write_output(
+    out_path: &str,
+    data: &[u8],
+    original_path: &str,
+    parameters: &settings::ProfileData,
+    created: SystemTime,
+    modified: SystemTime,
+) -> Result<(), AlicError> {
+    let replacing_original = is_same_file(original_path, out_path);
+    let path = Path::new(out_path);
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    if let Err(e) = fs::create_dir_all(dir) {
+        return Err(AlicError {
+            error: format!("Could not create folder {}: {e}", dir.display()),
+            error_type: AlicErrorType::Unknown,
+        });
+    }
+
+    let temp_path = write_temp_file(dir, path, data, parameters, created, modified)?;
+
+    // Whatever currently sits at the output path (the original on
+    // overwrite, or an unrelated/previous output file) goes to the trash
+    // so it can be recovered. A copy goes, so the output path stays
+    // occupied until the rename below replaces it.
+    if path.exists() {
+        match trash_copy(dir, path) {
+            Ok(trashed_path) => history::record(original_path, out_path, trashed_path),
+            // Trashing can fail on volumes without Trash support (network
+            // shares). Hard-fail only when we would destroy the original;
+            // stale outputs are replaced like previous versions did.
+            Err(e) if replacing_original => {
+                let _ = fs::remove_file(&temp_path);
                 return Err(AlicError {
-                    error: e.to_string(),
+                    error: format!("Could not move {out_path} to trash: {e}"),
                     error_type: AlicErrorType::Unknown,
                 });
             }
-        };
+            Err(_) => {}
+        }
     }
 
-    let out_size = compressed_size as u32;
-    Ok(CompressResult {
-        path: file.path,
-        out_size,
-        out_path,
-        result: "Success".to_string(),
-    })
+    if let Err(e) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(AlicError {
+            error: format!("Could not replace existing file at {out_path}: {e}"),
+            error_type: AlicErrorType::Unknown,
+        });
+    }
+    // Make the rename itself durable
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Write and sync `data` to a new hidden file in `dir`, returning its path.
+fn write_temp_file(
+    dir: &Path,
+    path: &Path,
+    data: &[u8],
+    parameters: &settings::ProfileData,
+    created: SystemTime,
+    modified: SystemTime,
+) -> Result<PathBuf, AlicError> {
+    let temp_path = unique_sibling(dir, path, "tmp");
+    let mut temp_file = fs::File::create_new(&temp_path).map_err(|e| AlicError {
+        error: e.to_string(),
+        error_type: AlicErrorType::Unknown,
+    })?;
+    let mut result = temp_file.write_all(data);
+    if result.is_ok() && parameters.keep_timestamps {
+        let times = fs::FileTimes::new()
+            .set_created(created)
+            .set_modified(modified);
+        result = temp_file.set_times(times);
+    }
+    if result.is_ok() {
+        result = temp_file.sync_all();
+    }
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(AlicError {
+            error: e.to_string(),
+            error_type: AlicErrorType::Unknown,
+        });
+    }
+    Ok(temp_path)
+}
+
+/// Move a copy of `path` to the trash, leaving `path` itself in place, and
+/// return where the copy ended up. The copy keeps the file's name by living
+/// in a hidden folder next to it.
+fn trash_copy(dir: &Path, path: &Path) -> Result<String, String> {
+    let backup_dir = unique_sibling(dir, path, "backup");
+    fs::create_dir(&backup_dir).map_err(|e| e.to_string())?;
+    let backup = backup_dir.join(path.file_name().unwrap_or_default());
+    // A hard link is instant and takes no space; fall back to copying on
+    // file systems without them
+    let result = fs::hard_link(path, &backup)
+        .or_else(|_| fs::copy(path, &backup).map(|_| ()))
+        .map_err(|e| e.to_string())
+        .and_then(|_| macos::trash_file(&backup.to_string_lossy()));
+    let _ = fs::remove_file(&backup);
+    let _ = fs::remove_dir(&backup_dir);
+    result
+}
+
+/// A hidden path next to `path` that no other write is using.
+fn unique_sibling(dir: &Path, path: &Path, kind: &str) -> PathBuf {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    dir.join(format!(".{name}.alic-{kind}-{}-{n}", std::process::id()))
 }
 
 fn read_image_info(path: &str) -> Result<ImageData, String> {
@@ -339,20 +904,24 @@ fn read_image_info(path: &str) -> Result<ImageData, String> {
         }
     };
     let format = match image::guess_format(&image_bytes) {
-        Ok(format) => format,
+        // The image crate doesn't know JPEG XL or HEIF
+        _ if is_jxl(&image_bytes) => Err(ImageType::JXL),
+        Ok(format) => Ok(format),
+        Err(_) if is_heif(&image_bytes) => Err(ImageType::HEIC),
         Err(err) => {
             return Err(format!("Unknown format: {err}"));
         }
     };
 
     let image_type = match format {
-        ImageFormat::Jpeg => ImageType::JPEG,
-        ImageFormat::Png => ImageType::PNG,
-        ImageFormat::WebP => ImageType::WEBP,
-        ImageFormat::Gif => ImageType::GIF,
-        ImageFormat::Tiff => ImageType::TIFF,
-        ImageFormat::Avif => ImageType::AVIF,
-        f => {
+        Err(image_type) => image_type,
+        Ok(ImageFormat::Jpeg) => ImageType::JPEG,
+        Ok(ImageFormat::Png) => ImageType::PNG,
+        Ok(ImageFormat::WebP) => ImageType::WEBP,
+        Ok(ImageFormat::Gif) => ImageType::GIF,
+        Ok(ImageFormat::Tiff) => ImageType::TIFF,
+        Ok(ImageFormat::Avif) => ImageType::AVIF,
+        Ok(f) => {
             let mime_type = f.to_mime_type();
             return Err(format!("Unsupported image type: {mime_type}"));
         }
@@ -367,39 +936,241 @@ fn read_image_info(path: &str) -> Result<ImageData, String> {
     ))
 }
 
+/// Resize the prepared source to each configured width, largest first.
+/// Widths wider than the source are skipped rather than upscaled; a source
+/// narrower than every width gets a single variant at its own width.
+fn responsive_variants(
+    data: Vec<u8>,
+    parameters: &settings::ProfileData,
+    is_animated: bool,
+) -> Result<Vec<Variant>, AlicError> {
+    let (source_width, _) = resize::image_dimensions(&data).ok_or_else(|| AlicError {
+        error: "Could not read image dimensions".to_string(),
+        error_type: AlicErrorType::ImageResizeError,
+    })?;
+    let mut widths: Vec<u32> = parameters
+        .responsive_widths
+        .iter()
+        .copied()
+        .filter(|w| *w > 0 && *w <= source_width)
+        .collect();
+    if widths.is_empty() {
+        widths.push(source_width);
+    }
+    widths.sort_unstable_by(|a, b| b.cmp(a));
+    widths.dedup();
+
+    widths
+        .into_iter()
+        .map(|width| {
+            // An unbounded height makes the fit depend on width alone
+            let resized = resize::resize(
+                data.clone(),
+                width,
+                u32::MAX,
+                false,
+                &parameters.background_fill,
+                is_animated,
+                parameters.gif_quality,
+            )?;
+            Ok(Variant {
+                suffix: format!("-{width}w"),
+                data: resized,
+            })
+        })
+        .collect()
+}
+
+/// Build an `<img srcset>` for a responsive set, or a `<picture>` with one
+/// `<source>` per extra format when several formats were written. The last
+/// format is the `<img>` fallback, and its largest width sets the intrinsic
+/// size.
+fn responsive_snippet(outputs: &[OutputFile]) -> String {
+    let mut formats: Vec<&ImageType> = Vec::new();
+    for output in outputs {
+        if !formats.contains(&&output.format) {
+            formats.push(&output.format);
+        }
+    }
+    let srcset = |format: &ImageType| {
+        let mut entries: Vec<&OutputFile> =
+            outputs.iter().filter(|o| o.format == *format).collect();
+        entries.sort_by_key(|o| o.width.unwrap_or(0));
+        entries
+            .iter()
+            .map(|o| format!("{} {}w", snippet_url(&o.path), o.width.unwrap_or(0)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let fallback = formats.pop().expect("responsive set has outputs");
+    let largest = outputs
+        .iter()
+        .filter(|o| o.format == *fallback)
+        .max_by_key(|o| o.width.unwrap_or(0))
+        .expect("fallback format has outputs");
+    let img = format!(
+        "<img src=\"{}\" srcset=\"{}\" sizes=\"100vw\" width=\"{}\" height=\"{}\" alt=\"\">",
+        snippet_url(&largest.path),
+        srcset(fallback),
+        largest.width.unwrap_or(0),
+        largest.height.unwrap_or(0),
+    );
+    if formats.is_empty() {
+        return img;
+    }
+
+    let mut picture = String::from("<picture>\n");
+    for format in formats {
+        picture.push_str(&format!(
+            "  <source type=\"{}\" srcset=\"{}\" sizes=\"100vw\">\n",
+            format.mime_type(),
+            srcset(format)
+        ));
+    }
+    picture.push_str(&format!("  {img}\n</picture>"));
+    picture
+}
+
+/// File name of an output, escaped for use in a srcset inside an HTML
+/// attribute.
+fn snippet_url(path: &str) -> String {
+    let name = Path::new(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    name.replace('%', "%25")
+        .replace(' ', "%20")
+        .replace(',', "%2C")
+        .replace('"', "%22")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+}
+
 fn get_out_path(
     parameters: &settings::ProfileData,
     path: &str,
+    root: Option<&str>,
     guessed_format: &ImageType,
+    target_format: &ImageType,
+    suffix: &str,
 ) -> String {
     let path = Path::new(&path);
+    let extension = output_extension(parameters, path, guessed_format, target_format);
+    let postfix = match parameters.add_postfix {
+        true => parameters.postfix.clone(),
+        false => "".to_string(),
+    };
+    let base = match output_directory(parameters, path, root) {
+        Some(dir) => dir
+            .join(path.file_stem().unwrap_or_default())
+            .to_string_lossy()
+            .to_string(),
+        None => remove_extension(path),
+    };
+    format!("{base}{suffix}{postfix}.{extension}")
+}
+
+fn output_extension(
+    parameters: &settings::ProfileData,
+    path: &Path,
+    guessed_format: &ImageType,
+    target_format: &ImageType,
+) -> String {
+    // Also when the source can't be written back as is (HEIC becomes JPEG)
+    if parameters.should_convert || target_format != guessed_format {
+        return target_format.preferred_extension().to_string();
+    }
     let file_extension = path
         .extension()
         .unwrap_or_default()
         .to_string_lossy()
         .to_string();
-    let original_extension = match guessed_format
+    match guessed_format
         .extensions()
         .contains(&file_extension.as_str())
     {
         true => file_extension,
         false => guessed_format.preferred_extension().to_string(),
-    };
-    let extension = match parameters.should_convert {
-        true => parameters
-            .convert_extension
-            .preferred_extension()
-            .to_string(),
-        false => original_extension,
-    };
-    let postfix = match parameters.add_postfix {
-        true => parameters.postfix.clone(),
-        false => "".to_string(),
-    };
-    format!("{}{postfix}.{extension}", remove_extension(path))
+    }
+}
+
+/// How the outputs of one image are named: the profile's postfix rule, or
+/// its filename template along with the dates the template can refer to.
+struct Naming<'a> {
+    parameters: &'a settings::ProfileData,
+    path: &'a str,
+    root: Option<&'a str>,
+    guessed_format: &'a ImageType,
+    template: Option<Template>,
+    date: NaiveDateTime,
+    taken: NaiveDateTime,
+}
+
+impl Naming<'_> {
+    /// The output path for `target_format`, or `None` when the template
+    /// refers to the encoded output (e.g. `{width}`) and `encoded` is `None`.
+    fn out_path(
+        &self,
+        target_format: &ImageType,
+        suffix: &str,
+        encoded: Option<&Encoded>,
+    ) -> Option<String> {
+        let Some(template) = &self.template else {
+            return Some(get_out_path(
+                self.parameters,
+                self.path,
+                self.root,
+                self.guessed_format,
+                target_format,
+                suffix,
+            ));
+        };
+        let path = Path::new(self.path);
+        let name = template.render(&TemplateValues {
+            name: &path.file_stem().unwrap_or_default().to_string_lossy(),
+            ext: &output_extension(self.parameters, path, self.guessed_format, target_format),
+            profile: &self.parameters.name,
+            date: self.date,
+            taken: self.taken,
+            encoded,
+        })?;
+        // Responsive suffixes go before the extension, like the postfix rule
+        let name = match name.rfind('.') {
+            Some(dot) if dot > 0 => format!("{}{suffix}{}", &name[..dot], &name[dot..]),
+            _ => format!("{name}{suffix}"),
+        };
+        let dir = output_directory(self.parameters, path, self.root)
+            .unwrap_or_else(|| path.parent().unwrap_or(Path::new("")).to_path_buf());
+        Some(dir.join(name).to_string_lossy().to_string())
+    }
+}
+
+/// The directory outputs go to when the profile has an output folder: the
+/// file's location relative to the dropped folder (`root`) is recreated
+/// under it, unless flattening is on or the file wasn't found in a folder.
+fn output_directory(
+    parameters: &settings::ProfileData,
+    path: &Path,
+    root: Option<&str>,
+) -> Option<PathBuf> {
+    if !parameters.should_output_dir || parameters.output_dir.is_empty() {
+        return None;
+    }
+    let output_dir = PathBuf::from(&parameters.output_dir);
+    if parameters.flatten_output {
+        return Some(output_dir);
+    }
+    let relative = root
+        .and_then(|root| path.parent()?.strip_prefix(root).ok())
+        .unwrap_or(Path::new(""));
+    Some(output_dir.join(relative))
 }
 
-fn create_cs_parameters(parameters: &settings::ProfileData) -> CSParameters {
+fn create_cs_parameters(
+    parameters: &settings::ProfileData,
+    target_format: &ImageType,
+) -> CSParameters {
     let mut cs = CSParameters::new();
     cs.jpeg.quality = parameters.jpeg_quality;
     cs.png.quality = parameters.png_quality;
@@ -408,11 +1179,356 @@ fn create_cs_parameters(parameters: &settings::ProfileData) -> CSParameters {
     cs.png.optimize = !parameters.enable_lossy;
     cs.jpeg.optimize = !parameters.enable_lossy;
     cs.webp.lossless = !parameters.enable_lossy;
-    cs.keep_metadata = parameters.keep_metadata;
-    cs.jpeg.preserve_icc = true;
+    cs.keep_metadata = caesium_keeps_metadata(parameters, target_format);
+    cs.jpeg.preserve_icc = false;
+    cs.jpeg.progressive = parameters.jpeg_progressive;
+    cs.jpeg.chroma_subsampling = match parameters.jpeg_chroma_subsampling {
+        settings::ChromaSubsampling::Auto => ChromaSubsampling::Auto,
+        settings::ChromaSubsampling::CS444 => ChromaSubsampling::CS444,
+        settings::ChromaSubsampling::CS422 => ChromaSubsampling::CS422,
+        settings::ChromaSubsampling::CS420 => ChromaSubsampling::CS420,
+    };
+    cs.png.optimization_level = parameters.png_optimization_level;
     cs
 }
 
+/// Formats to encode an image to: the source format, the conversion target,
+/// or every format in `convert_formats` for the multi-format modes.
+fn target_formats(parameters: &settings::ProfileData, source_format: &ImageType) -> Vec<ImageType> {
+    if parameters.should_convert {
+        return parameters.convert_targets();
+    }
+    // HEIC can't be written, so photos that aren't converted become JPEG
+    match source_format {
+        ImageType::HEIC => vec![ImageType::JPEG],
+        format => vec![format.clone()],
+    }
+}
+
+/// Encode `data` as `target_format`, choosing the quality according to the
+/// profile's quality mode. Returns the encoded bytes and the quality used.
+fn encode_target(
+    data: &[u8],
+    parameters: &settings::ProfileData,
+    metadata: &Metadata,
+    target_format: &ImageType,
+    source_format: &ImageType,
+    job: &Job,
+) -> Result<(Vec<u8>, Option<u32>), AlicError> {
+    let should_convert = target_format != source_format;
+    if parameters.should_target_size {
+        encode_to_target_size(
+            data,
+            parameters,
+            metadata,
+            target_format,
+            source_format,
+            job,
+        )
+    } else if parameters.should_target_similarity {
+        encode_to_target_similarity(
+            data,
+            parameters,
+            metadata,
+            target_format,
+            source_format,
+            job,
+        )
+    } else {
+        job.check()?;
+        let encoded = encode_with_quality(
+            data,
+            parameters,
+            metadata,
+            target_format,
+            should_convert,
+            job,
+            None,
+        )?;
+        Ok((
+            encoded,
+            quality_for(parameters, target_format, source_format),
+        ))
+    }
+}
+
+fn encode(
+    data: Vec<u8>,
+    parameters: &settings::ProfileData,
+    metadata: &Metadata,
+    target_format: &ImageType,
+    should_convert: bool,
+    job: &Job,
+) -> Result<Vec<u8>, String> {
+    // AVIF uses ravif and JXL libjxl directly instead of libcaesium
+    if *target_format == ImageType::AVIF {
+        compress_avif(&data, parameters, metadata.exif(), job)
+    } else if *target_format == ImageType::JXL {
+        compress_jxl(&data, parameters, job)
+    } else if *target_format == ImageType::HEIC {
+        Err("HEIC output is not supported".to_string())
+    } else if *target_format == ImageType::WEBP && animation::is_animated(&data) {
+        compress_animated_webp(&data, parameters)
+    } else if *target_format == ImageType::PNG && animation::is_animated(&data) {
+        compress_apng(&data, parameters)
+    } else if *target_format == ImageType::GIF && should_convert && animation::is_animated(&data) {
+        // libcaesium keeps GIF frames when compressing, but not when converting
+        let gif = animation::decode(&data)
+            .and_then(|animation| {
+                animation::encode(&animation, ImageFormat::Gif, parameters.gif_quality)
+            })
+            .map_err(|e| e.error)?;
+        compress_image(
+            gif,
+            create_cs_parameters(parameters, &ImageType::GIF),
+            ImageType::GIF,
+        )
+    } else if *target_format == ImageType::WEBP && needs_webp_encoder(&data, parameters) {
+        compress_webp(&data, parameters)
+    } else if *target_format == ImageType::PNG && needs_png_quantizer(&data, parameters) {
+        compress_png(&data, parameters)
+    } else if should_convert {
+        convert_image(
+            data,
+            create_cs_parameters(parameters, target_format),
+            target_format.clone(),
+        )
+    } else {
+        compress_image(
+            data,
+            create_cs_parameters(parameters, target_format),
+            target_format.clone(),
+        )
+    }
+}
+
+/// Whether libcaesium should keep the source's metadata. JPEG, PNG and WebP
+/// get only the kinds the profile keeps written back afterwards. libcaesium
+/// can only keep all of it or none, so other formats keep it when the
+/// profile keeps everything.
+fn caesium_keeps_metadata(parameters: &settings::ProfileData, target_format: &ImageType) -> bool {
+    !Metadata::writes(target_format)
+        && parameters.metadata == settings::MetadataPolicy::all()
+        && parameters.color_profile != settings::ColorProfilePolicy::Strip
+}
+
+/// Binary search the encoder quality for the largest output that still fits
+/// in `target_size_kb`. Returns the encoded bytes and the quality used.
+fn encode_to_target_size(
+    data: &[u8],
+    parameters: &settings::ProfileData,
+    metadata: &Metadata,
+    target_format: &ImageType,
+    source_format: &ImageType,
+    job: &Job,
+) -> Result<(Vec<u8>, Option<u32>), AlicError> {
+    let should_convert = target_format != source_format;
+    // The marker is added after the quality is picked, so leave room for it
+    let reserved = match parameters.should_mark_outputs {
+        true => marker::marker_len(target_format),
+        false => 0,
+    };
+    let budget = (parameters.target_size_kb as usize * 1024).saturating_sub(reserved);
+    let encode_at = |quality| {
+        job.check()?;
+        encode_with_quality(
+            data,
+            parameters,
+            metadata,
+            target_format,
+            should_convert,
+            job,
+            quality,
+        )
+    };
+
+    // Lossless encoders and JPEG XL repacks ignore quality, so there is
+    // nothing to search
+    if !has_tunable_quality(parameters, target_format, source_format) {
+        let encoded = encode_at(None)?;
+        if encoded.len() > budget {
+            return Err(target_size_error(parameters, encoded.len()));
+        }
+        return Ok((encoded, None));
+    }
+
+    let mut best = None;
+    let mut smallest = usize::MAX;
+    let (mut low, mut high) = (1_u32, 100_u32);
+    while low <= high {
+        let quality = (low + high) / 2;
+        let encoded = encode_at(Some(quality))?;
+        debug!(
+            "Target size: quality {quality} is {} bytes (budget {budget})",
+            encoded.len()
+        );
+        smallest = smallest.min(encoded.len());
+        if encoded.len() <= budget {
+            best = Some((encoded, Some(quality)));
+            low = quality + 1;
+        } else {
+            high = quality - 1;
+        }
+    }
+    best.ok_or_else(|| target_size_error(parameters, smallest))
+}
+
+/// Binary search the encoder quality for the smallest output whose decoded
+/// pixels keep at least `target_similarity` SSIM against the source. Falls
+/// back to the highest quality when nothing reaches the threshold.
+fn encode_to_target_similarity(
+    data: &[u8],
+    parameters: &settings::ProfileData,
+    metadata: &Metadata,
+    target_format: &ImageType,
+    source_format: &ImageType,
+    job: &Job,
+) -> Result<(Vec<u8>, Option<u32>), AlicError> {
+    let should_convert = target_format != source_format;
+    let encode_at = |quality| {
+        job.check()?;
+        encode_with_quality(
+            data,
+            parameters,
+            metadata,
+            target_format,
+            should_convert,
+            job,
+            quality,
+        )
+    };
+
+    if !has_tunable_quality(parameters, target_format, source_format) {
+        return Ok((encode_at(None)?, None));
+    }
+
+    // The image crate is built with the AVIF encoder only, so AVIF output
+    // can't be decoded back for comparison
+    if *target_format == ImageType::AVIF {
+        return Err(AlicError {
+            error: "Target similarity is not supported for AVIF output.".to_string(),
+            error_type: AlicErrorType::UnsupportedFileType,
+        });
+    }
+
+    let (source, _) = resize::read_image(data)?;
+    let mut best = None;
+    let mut highest_rejected = None;
+    let (mut low, mut high) = (1_u32, 100_u32);
+    while low <= high {
+        let quality = (low + high) / 2;
+        let encoded = encode_at(Some(quality))?;
+        let decoded = decode_output(&encoded, target_format)?;
+        let score = ssim::ssim(&source, &decoded);
+        debug!(
+            "Target similarity: quality {quality} scored {score:.4} (target {})",
+            parameters.target_similarity
+        );
+        if score >= parameters.target_similarity {
+            best = Some((encoded, Some(quality)));
+            high = quality - 1;
+        } else {
+            highest_rejected = Some((encoded, Some(quality)));
+            low = quality + 1;
+        }
+    }
+    // Every candidate failed, so the last one tried was quality 100
+    Ok(best
+        .or(highest_rejected)
+        .expect("quality search ran at least once"))
+}
+
+fn encode_with_quality(
+    data: &[u8],
+    parameters: &settings::ProfileData,
+    metadata: &Metadata,
+    target_format: &ImageType,
+    should_convert: bool,
+    job: &Job,
+    quality: Option<u32>,
+) -> Result<Vec<u8>, AlicError> {
+    let mut params = parameters.clone();
+    if let Some(quality) = quality {
+        set_quality(&mut params, target_format, quality);
+    }
+    encode(
+        data.to_vec(),
+        &params,
+        metadata,
+        target_format,
+        should_convert,
+        job,
+    )
+    .map(|encoded| metadata.write(encoded, target_format))
+    .map_err(|e| AlicError {
+        error: e,
+        error_type: AlicErrorType::Unknown,
+    })
+}
+
+fn target_size_error(parameters: &settings::ProfileData, size: usize) -> AlicError {
+    AlicError {
+        error: format!(
+            "Image cannot fit in {} KB, smallest output was {} KB.",
+            parameters.target_size_kb,
+            size.div_ceil(1024)
+        ),
+        error_type: AlicErrorType::TargetSizeNotMet,
+    }
+}
+
+/// Whether changing the quality setting changes the output for this format.
+/// A JPEG repacked into JPEG XL keeps its own quality.
+fn has_tunable_quality(
+    parameters: &settings::ProfileData,
+    format: &ImageType,
+    source_format: &ImageType,
+) -> bool {
+    match format {
+        ImageType::AVIF | ImageType::GIF => true,
+        ImageType::JPEG | ImageType::PNG | ImageType::WEBP => parameters.enable_lossy,
+        ImageType::JXL => parameters.enable_lossy && !repacks_into_jxl(parameters, source_format),
+        ImageType::TIFF | ImageType::HEIC => false,
+    }
+}
+
+/// Whether a JPEG XL output repacks the source JPEG instead of encoding
+/// its pixels.
+fn repacks_into_jxl(parameters: &settings::ProfileData, source_format: &ImageType) -> bool {
+    parameters.jxl_lossless_jpeg && *source_format == ImageType::JPEG
+}
+
+fn quality_for(
+    parameters: &settings::ProfileData,
+    format: &ImageType,
+    source_format: &ImageType,
+) -> Option<u32> {
+    if !has_tunable_quality(parameters, format, source_format) {
+        return None;
+    }
+    match format {
+        ImageType::JPEG => Some(parameters.jpeg_quality),
+        ImageType::PNG => Some(parameters.png_quality),
+        ImageType::WEBP => Some(parameters.webp_quality),
+        ImageType::GIF => Some(parameters.gif_quality),
+        ImageType::AVIF => Some(parameters.avif_quality),
+        ImageType::JXL => Some(parameters.jxl_quality),
+        ImageType::TIFF | ImageType::HEIC => None,
+    }
+}
+
+fn set_quality(parameters: &mut settings::ProfileData, format: &ImageType, quality: u32) {
+    match format {
+        ImageType::JPEG => parameters.jpeg_quality = quality,
+        ImageType::PNG => parameters.png_quality = quality,
+        ImageType::WEBP => parameters.webp_quality = quality,
+        ImageType::GIF => parameters.gif_quality = quality,
+        ImageType::AVIF => parameters.avif_quality = quality,
+        ImageType::JXL => parameters.jxl_quality = quality,
+        ImageType::TIFF | ImageType::HEIC => {}
+    }
+}
+
 // Simplified wrapper functions
 fn compress_image(
     original_img_data: Vec<u8>,
@@ -443,54 +1559,342 @@ fn convert_image(
     }
 }
 
+/// Encode an AVIF with ravif, which embeds `exif` as it is. AVIF has no
+/// place for the rest of the metadata the profile keeps.
 fn compress_avif(
     original_img_data: &[u8],
     parameters: &settings::ProfileData,
-    parallel_images: i32,
+    exif: Option<&[u8]>,
+    job: &Job,
 ) -> Result<Vec<u8>, String> {
-    use image::ImageEncoder;
-    use image::ImageReader;
-    use image::codecs::avif::AvifEncoder;
-    use std::io::Cursor;
-
-    // Decode the input image
-    let img = ImageReader::new(Cursor::new(original_img_data))
-        .with_guessed_format()
-        .map_err(|e| format!("Error reading image: {e}"))?
-        .decode()
-        .map_err(|e| format!("Error decoding image: {e}"))?;
+    use ravif::{BitDepth, Encoder, Img, RGBA8};
 
-    let rgba = img.to_rgba8();
-    let width = rgba.width();
-    let height = rgba.height();
-
-    // Calculate thread count for AVIF encoding
-    let cpu_count = num_cpus();
-    let avif_threads = if parallel_images <= 1 {
-        // Single image, use all available threads
-        None
-    } else {
-        // Multiple images in parallel, divide threads
-        Some(std::cmp::max(1, cpu_count / parallel_images as usize))
-    };
+    let avif_threads = encoder_threads(job);
 
     // Quality: 1-100 (1 worst, 100 best)
-    let quality = parameters.avif_quality.clamp(1, 100) as u8;
+    let quality = parameters.avif_quality.clamp(1, 100) as f32;
+    let alpha_quality = match parameters.should_avif_alpha_quality {
+        true => parameters.avif_alpha_quality.clamp(1, 100) as f32,
+        false => quality,
+    };
     // Speed: 1-10 (1 slowest/best quality, 10 fastest/worst quality)
-    let speed = 4_u8;
-
-    // Create output buffer
-    let mut output = Vec::new();
-    debug!(
-        "Using {avif_threads:?} AVIF threads with {cpu_count} total threads for {parallel_images} images."
-    );
+    let speed = parameters.avif_speed.clamp(1, 10);
+
+    debug!("Using {avif_threads:?} AVIF threads.");
+
+    // ravif only encodes still images, so animations go to rav1e as a
+    // sequence
+    if animation::is_animated(original_img_data) {
+        let animation = animation::decode(original_img_data).map_err(|e| e.error)?;
+        let config = animation::AvifConfig {
+            quality,
+            alpha_quality,
+            speed,
+            threads: avif_threads,
+        };
+        return animation::encode_avif(&animation, &config, exif);
+    }
 
-    let encoder = AvifEncoder::new_with_speed_quality(&mut output, speed, quality)
+    let (img, _) = resize::read_image(original_img_data).map_err(|e| e.error)?;
+    let rgba = img.to_rgba8();
+    let pixels: Vec<RGBA8> = rgba
+        .pixels()
+        .map(|p| RGBA8::new(p[0], p[1], p[2], p[3]))
+        .collect();
+
+    let encoder = Encoder::new()
+        .with_quality(quality)
+        .with_alpha_quality(alpha_quality)
+        .with_speed(speed)
+        .with_bit_depth(BitDepth::Eight)
         .with_num_threads(avif_threads);
-    encoder
-        .write_image(&rgba, width, height, image::ExtendedColorType::Rgba8)
+    let encoder = match exif {
+        Some(exif) => encoder.with_exif(exif),
+        None => encoder,
+    };
+    let encoded = encoder
+        .encode_rgba(Img::new(
+            &pixels[..],
+            rgba.width() as usize,
+            rgba.height() as usize,
+        ))
         .map_err(|e| format!("Error encoding AVIF: {e}"))?;
-    Ok(output)
+    Ok(encoded.avif_file)
+}
+
+/// Threads for encoders with their own thread pool (AVIF, JXL): all of them
+/// for a single image, otherwise an even share of the images running right
+/// now, so a slow image left at the end of a batch gets every CPU.
+fn encoder_threads(job: &Job) -> Option<usize> {
+    match job.images_running() {
+        0 | 1 => None,
+        images => Some(std::cmp::max(1, num_cpus() / images)),
+    }
+}
+
+/// Whether `format` comes out with the source's ICC profile. JPEG, PNG and
+/// WebP have it written back; JPEG XL only keeps it when repacking a JPEG.
+fn carries_icc(
+    format: &ImageType,
+    parameters: &settings::ProfileData,
+    source_format: &ImageType,
+) -> bool {
+    match format {
+        ImageType::JPEG | ImageType::PNG | ImageType::WEBP => true,
+        ImageType::JXL => repacks_into_jxl(parameters, source_format),
+        _ => false,
+    }
+}
+
+fn compress_jxl(
+    original_img_data: &[u8],
+    parameters: &settings::ProfileData,
+    job: &Job,
+) -> Result<Vec<u8>, String> {
+    use jpegxl_rs::{ThreadsRunner, encoder_builder};
+
+    let runner = ThreadsRunner::new(None, encoder_threads(job));
+    let mut builder = encoder_builder();
+    if let Some(runner) = &runner {
+        builder.parallel_runner(runner);
+    }
+    let encode_error = |e: jpegxl_rs::encode::EncodeError| format!("Error encoding JXL: {e}");
+
+    // Repack the JPEG's own DCT coefficients instead of decoding them, so the
+    // original JPEG can be rebuilt from the JXL bit for bit
+    if parameters.jxl_lossless_jpeg
+        && image::guess_format(original_img_data).is_ok_and(|f| f == ImageFormat::Jpeg)
+    {
+        let jpeg = Metadata::narrow_jpeg(
+            original_img_data.to_vec(),
+            &parameters.metadata,
+            parameters.color_profile == settings::ColorProfilePolicy::Strip,
+        );
+        let mut encoder = builder.build().map_err(encode_error)?;
+        let encoded = encoder.encode_jpeg(&jpeg).map_err(encode_error)?;
+        return Ok(encoded.data);
+    }
+
+    let (img, _) = resize::read_image(original_img_data).map_err(|e| e.error)?;
+    let has_alpha = img.color().has_alpha();
+    let pixels = match has_alpha {
+        true => img.to_rgba8().into_raw(),
+        false => img.to_rgb8().into_raw(),
+    };
+    let lossless = !parameters.enable_lossy;
+    let mut encoder = builder
+        .has_alpha(has_alpha)
+        .lossless(lossless)
+        .uses_original_profile(lossless)
+        .quality(jxl_distance(parameters.jxl_quality))
+        .build()
+        .map_err(encode_error)?;
+    let encoded = encoder
+        .encode::<u8, u8>(&pixels, img.width(), img.height())
+        .map_err(encode_error)?;
+    Ok(encoded.data)
+}
+
+/// libjxl's `JxlEncoderDistanceFromQuality`: map a 1-100 quality to the
+/// Butteraugli distance the encoder takes, where 0 is lossless and 1 is
+/// visually lossless (about quality 90).
+fn jxl_distance(quality: u32) -> f32 {
+    let quality = quality.clamp(1, 100) as f32;
+    if quality >= 100.0 {
+        0.0
+    } else if quality >= 30.0 {
+        0.1 + (100.0 - quality) * 0.09
+    } else {
+        6.4 + 2.5_f32.powf((30.0 - quality) / 5.0) / 6.25
+    }
+}
+
+/// Decode JPEG XL to PNG, which the rest of the pipeline can read. The ICC
+/// profile is kept so colors don't shift.
+fn decode_jxl(data: &[u8]) -> Result<Vec<u8>, String> {
+    use image::codecs::png::PngEncoder;
+    use image::{DynamicImage, ImageBuffer, ImageEncoder};
+
+    let decode_error = |e: jpegxl_rs::decode::DecodeError| format!("Error decoding JXL: {e}");
+    let decoder = jpegxl_rs::decoder_builder()
+        .icc_profile(true)
+        .build()
+        .map_err(decode_error)?;
+    let (metadata, pixels) = decoder.decode_with::<u8>(data).map_err(decode_error)?;
+    let (width, height) = (metadata.width, metadata.height);
+    let image = match metadata.num_color_channels + u32::from(metadata.has_alpha_channel) {
+        1 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        2 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+        3 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        _ => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+    }
+    .ok_or_else(|| "Error decoding JXL: unexpected pixel data".to_string())?;
+
+    let mut out = Vec::new();
+    let mut encoder = PngEncoder::new(&mut out);
+    if let Some(icc_profile) = metadata.icc_profile {
+        // Only fails for encoders without ICC support, which PNG has
+        let _ = encoder.set_icc_profile(icc_profile);
+    }
+    image
+        .write_with_encoder(encoder)
+        .map_err(|e| format!("Error decoding JXL: {e}"))?;
+    Ok(out)
+}
+
+/// Codestream or ISO BMFF container signature.
+fn is_jxl(data: &[u8]) -> bool {
+    const CONTAINER: [u8; 12] = [
+        0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+    ];
+    data.starts_with(&[0xFF, 0x0A]) || data.starts_with(&CONTAINER)
+}
+
+/// An ISO BMFF `ftyp` box with a HEIF brand. AVIF shares the container but
+/// is recognized by the image crate before this is checked.
+fn is_heif(data: &[u8]) -> bool {
+    const BRANDS: [&[u8]; 8] = [
+        b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+    ];
+    data.get(4..8) == Some(b"ftyp".as_slice())
+        && data.get(8..12).is_some_and(|brand| BRANDS.contains(&brand))
+}
+
+/// Decode an encoded output to compare it with the source.
+fn decode_output(data: &[u8], format: &ImageType) -> Result<image::DynamicImage, AlicError> {
+    let decoded = match format {
+        ImageType::JXL => resize::read_image(&decode_jxl(data).map_err(|error| AlicError {
+            error,
+            error_type: AlicErrorType::Unknown,
+        })?)?,
+        _ => resize::read_image(data)?,
+    };
+    Ok(decoded.0)
+}
+
+/// libcaesium always encodes WebP at method 4 without near-lossless, so
+/// encode with libwebp directly when either is wanted. Animations are left
+/// to libcaesium, since this path only sees the first frame.
+fn needs_webp_encoder(data: &[u8], parameters: &settings::ProfileData) -> bool {
+    // Near-lossless only applies to the lossless encoder, and 100 is off
+    let near_lossless = !parameters.enable_lossy && parameters.webp_near_lossless < 100;
+    (parameters.webp_method != 4 || near_lossless) && !animation::is_animated(data)
+}
+
+fn compress_webp(
+    original_img_data: &[u8],
+    parameters: &settings::ProfileData,
+) -> Result<Vec<u8>, String> {
+    let (img, _) = resize::read_image(original_img_data).map_err(|e| e.error)?;
+    let rgba = img.to_rgba8();
+
+    let config = webp_config(parameters)?;
+    let encoded = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+        .encode_advanced(&config)
+        .map_err(|e| format!("Error encoding WebP: {e:?}"))?;
+    Ok(encoded.to_vec())
+}
+
+/// Encode an animated GIF, PNG or WebP as an animated WebP, keeping every
+/// frame's delay and the loop count. libcaesium and the still encoders only
+/// keep the first frame.
+fn compress_animated_webp(
+    original_img_data: &[u8],
+    parameters: &settings::ProfileData,
+) -> Result<Vec<u8>, String> {
+    let animation = animation::decode(original_img_data).map_err(|e| e.error)?;
+    animation::encode_webp(&animation, &webp_config(parameters)?)
+}
+
+/// Write an animation as an APNG and recompress it with oxipng, which keeps
+/// every frame. oxipng skips its color reductions for APNGs, so animations
+/// stay lossless.
+fn compress_apng(
+    original_img_data: &[u8],
+    parameters: &settings::ProfileData,
+) -> Result<Vec<u8>, String> {
+    let apng = match image::guess_format(original_img_data) {
+        Ok(ImageFormat::Png) => original_img_data.to_vec(),
+        _ => animation::decode(original_img_data)
+            .and_then(|animation| {
+                animation::encode(&animation, ImageFormat::Png, parameters.gif_quality)
+            })
+            .map_err(|e| e.error)?,
+    };
+    let options = oxipng::Options::from_preset(parameters.png_optimization_level.min(6));
+    oxipng::optimize_from_memory(&apng, &options).map_err(|e| format!("Error optimizing PNG: {e}"))
+}
+
+fn webp_config(parameters: &settings::ProfileData) -> Result<webp::WebPConfig, String> {
+    let mut config =
+        webp::WebPConfig::new().map_err(|_| "Error creating WebP config".to_string())?;
+    let lossless = !parameters.enable_lossy;
+    config.lossless = i32::from(lossless);
+    config.alpha_compression = i32::from(!lossless);
+    config.quality = parameters.webp_quality as f32;
+    config.method = parameters.webp_method.min(6) as i32;
+    // Only used by the lossless encoder, 100 turns it off
+    config.near_lossless = parameters.webp_near_lossless.min(100) as i32;
+    Ok(config)
+}
+
+/// libcaesium always quantizes lossy PNGs to 256 colors with full dithering,
+/// so quantize here when fewer colors or less dithering is wanted.
+fn needs_png_quantizer(data: &[u8], parameters: &settings::ProfileData) -> bool {
+    parameters.enable_lossy
+        && (parameters.png_colors < 256 || parameters.png_dithering < 1.0)
+        && !animation::is_animated(data)
+}
+
+fn compress_png(
+    original_img_data: &[u8],
+    parameters: &settings::ProfileData,
+) -> Result<Vec<u8>, String> {
+    let (img, _) = resize::read_image(original_img_data).map_err(|e| e.error)?;
+    let rgba = img.to_rgba8();
+    let (width, height) = (rgba.width(), rgba.height());
+    let pixels: Vec<imagequant::RGBA> = rgba
+        .pixels()
+        .map(|p| imagequant::RGBA::new(p[0], p[1], p[2], p[3]))
+        .collect();
+
+    let quantize_error = |e: imagequant::Error| format!("Error quantizing PNG: {e}");
+    let mut attributes = imagequant::new();
+    attributes
+        .set_max_colors(parameters.png_colors.clamp(2, 256))
+        .map_err(quantize_error)?;
+    attributes
+        .set_quality(0, parameters.png_quality.min(100) as u8)
+        .map_err(quantize_error)?;
+    let mut image = attributes
+        .new_image(pixels, width as usize, height as usize, 0.0)
+        .map_err(quantize_error)?;
+    let mut quantized = attributes.quantize(&mut image).map_err(quantize_error)?;
+    quantized
+        .set_dithering_level(parameters.png_dithering.clamp(0.0, 1.0) as f32)
+        .map_err(quantize_error)?;
+    let (palette, indices) = quantized.remapped(&mut image).map_err(quantize_error)?;
+
+    let png_error = |e: png::EncodingError| format!("Error encoding PNG: {e}");
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b])
+            .collect::<Vec<u8>>(),
+    );
+    encoder.set_trns(palette.iter().map(|c| c.a).collect::<Vec<u8>>());
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&indices).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+
+    let options = oxipng::Options::from_preset(parameters.png_optimization_level.min(6));
+    let optimized = oxipng::optimize_from_memory(&encoded, &options)
+        .map_err(|e| format!("Error optimizing PNG: {e}"))?;
+    Ok(optimized)
 }
 
 pub fn num_cpus() -> usize {
@@ -526,7 +1930,8 @@ fn remove_extension(path: &Path) -> String {
 #[tauri::command]
 #[specta::specta]
 pub async fn get_all_images(app: tauri::AppHandle, path: String) -> Result<(), String> {
-    let on_event = |path: String| AddFileEvent(path).emit(&app).unwrap();
+    let on_event =
+        |file: String, root: Option<String>| AddFileEvent { path: file, root }.emit(&app).unwrap();
 
     let file = Path::new(&path);
     if !file.exists() {
@@ -537,10 +1942,10 @@ pub async fn get_all_images(app: tauri::AppHandle, path: String) -> Result<(), S
             let _ = ErrorEvent(format!("Unsupported file: {path}")).emit(&app);
             return Ok(());
         }
-        on_event(path);
+        on_event(path, None);
         return Ok(());
     }
-    find_images(path, &on_event);
+    find_images(&path, &|file| on_event(file, Some(path.clone())));
     Ok(())
 }
 
@@ -654,7 +2059,9 @@ fn is_image(path: &Path) -> bool {
     if !path.is_file() {
         return false;
     }
-    let supported_exts = ["png", "jpeg", "jpg", "gif", "webp", "tiff", "tif", "avif"];
+    let supported_exts = [
+        "png", "jpeg", "jpg", "gif", "webp", "tiff", "tif", "avif", "jxl", "heic", "heif",
+    ];
     let ext = path
         .extension()
         .unwrap_or_default()
@@ -677,40 +2084,602 @@ mod tests {
         assert_eq!(result, "jpg".to_string());
     }
 
+    #[test]
+    fn test_jxl() {
+        assert!(is_jxl(&[0xFF, 0x0A, 0xFA]));
+        assert!(is_jxl(b"\0\0\0\x0cJXL \r\n\x87\n\0\0\0\x14ftypjxl "));
+        assert!(!is_jxl(&[0xFF, 0xD8, 0xFF]));
+        assert_eq!(ImageType::JXL.extensions(), ["jxl"]);
+        assert_eq!(jxl_distance(100), 0.0);
+        assert!((jxl_distance(90) - 1.0).abs() < 1e-5);
+        assert!(jxl_distance(10) > jxl_distance(30));
+    }
+
+    #[test]
+    fn test_animated_webp() {
+        use image::codecs::gif::{GifEncoder, Repeat};
+        use image::codecs::webp::WebPDecoder;
+        use image::metadata::LoopCount;
+        use image::{AnimationDecoder, Delay, Frame, Rgba, RgbaImage};
+        use std::io::Cursor;
+
+        let mut gif = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            encoder.set_repeat(Repeat::Finite(2)).unwrap();
+            for (color, delay) in [(0, 100), (128, 200), (255, 300)] {
+                let pixels = RgbaImage::from_pixel(16, 16, Rgba([color, 0, 0, 255]));
+                let delay = Delay::from_numer_denom_ms(delay, 1);
+                encoder
+                    .encode_frame(Frame::from_parts(pixels, 0, 0, delay))
+                    .unwrap();
+            }
+        }
+
+        let mut parameters = settings::ProfileData::new();
+        parameters.should_convert = true;
+        parameters.convert_extension = ImageType::WEBP;
+        let webp = encode(
+            gif.clone(),
+            &parameters,
+            &Metadata::default(),
+            &ImageType::WEBP,
+            true,
+            &Job::untracked(),
+        )
+        .unwrap();
+        let decoder = WebPDecoder::new(Cursor::new(&webp)).unwrap();
+        assert!(matches!(decoder.loop_count(), LoopCount::Finite(n) if n.get() == 3));
+        let delays: Vec<u32> = decoder
+            .into_frames()
+            .collect_frames()
+            .unwrap()
+            .iter()
+            .map(|frame| frame.delay().numer_denom_ms().0)
+            .collect();
+        assert_eq!(delays, [100, 200, 300]);
+
+        parameters.avif_speed = 10;
+        let avif = encode(
+            gif,
+            &parameters,
+            &Metadata::default(),
+            &ImageType::AVIF,
+            true,
+            &Job::untracked(),
+        )
+        .unwrap();
+        assert_eq!(&avif[4..12], b"ftypavis");
+    }
+
+    #[test]
+    fn test_heif() {
+        assert!(is_heif(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"));
+        assert!(is_heif(b"\0\0\0\x18ftypmif1\0\0\0\0mif1heic"));
+        assert!(!is_heif(b"\0\0\0\x18ftypisom\0\0\0\0isomiso2"));
+        assert_eq!(
+            target_formats(&settings::ProfileData::new(), &ImageType::HEIC),
+            vec![ImageType::JPEG]
+        );
+    }
+
+    #[test]
+    fn test_set_quality() {
+        let mut parameters = settings::ProfileData::new();
+        set_quality(&mut parameters, &ImageType::WEBP, 35);
+        assert_eq!(parameters.webp_quality, 35);
+        let png = &ImageType::PNG;
+        assert_eq!(quality_for(&parameters, &ImageType::WEBP, png), Some(35));
+        assert_eq!(quality_for(&parameters, &ImageType::TIFF, png), None);
+
+        // Repacking a JPEG into JPEG XL keeps the JPEG's quality
+        parameters.jxl_lossless_jpeg = true;
+        assert_eq!(quality_for(&parameters, &ImageType::JXL, png), Some(80));
+        assert_eq!(
+            quality_for(&parameters, &ImageType::JXL, &ImageType::JPEG),
+            None
+        );
+
+        parameters.enable_lossy = false;
+        assert_eq!(quality_for(&parameters, &ImageType::WEBP, png), None);
+        assert_eq!(quality_for(&parameters, &ImageType::AVIF, png), Some(80));
+    }
+
+    #[test]
+    fn test_compress_webp() {
+        let mut source = Vec::new();
+        image::RgbaImage::from_pixel(16, 16, image::Rgba([200, 40, 40, 128]))
+            .write_to(&mut std::io::Cursor::new(&mut source), ImageFormat::Png)
+            .unwrap();
+        let mut parameters = settings::ProfileData::new();
+        assert!(!needs_webp_encoder(&source, &parameters));
+
+        parameters.webp_method = 6;
+        assert!(needs_webp_encoder(&source, &parameters));
+        let encoded = compress_webp(&source, &parameters).unwrap();
+        assert_eq!(image::guess_format(&encoded).unwrap(), ImageFormat::WebP);
+
+        // Near-lossless is only wanted from the lossless encoder
+        parameters.webp_method = 4;
+        parameters.webp_near_lossless = 60;
+        assert!(!needs_webp_encoder(&source, &parameters));
+        parameters.enable_lossy = false;
+        assert!(needs_webp_encoder(&source, &parameters));
+        let encoded = compress_webp(&source, &parameters).unwrap();
+        assert_eq!(resize::image_dimensions(&encoded), Some((16, 16)));
+    }
+
+    #[test]
+    fn test_target_formats() {
+        let mut parameters = settings::ProfileData::new();
+        assert_eq!(
+            target_formats(&parameters, &ImageType::PNG),
+            vec![ImageType::PNG]
+        );
+
+        parameters.should_convert = true;
+        parameters.convert_extension = ImageType::AVIF;
+        assert_eq!(
+            target_formats(&parameters, &ImageType::PNG),
+            vec![ImageType::AVIF]
+        );
+
+        parameters.convert_mode = settings::ConvertMode::Smallest;
+        parameters.convert_formats = vec![ImageType::WEBP, ImageType::JPEG, ImageType::WEBP];
+        assert_eq!(
+            target_formats(&parameters, &ImageType::PNG),
+            vec![ImageType::WEBP, ImageType::JPEG]
+        );
+
+        parameters.convert_mode = settings::ConvertMode::All;
+        assert_eq!(
+            target_formats(&parameters, &ImageType::PNG),
+            vec![ImageType::WEBP, ImageType::JPEG]
+        );
+
+        parameters.convert_formats = vec![];
+        assert_eq!(
+            target_formats(&parameters, &ImageType::PNG),
+            vec![ImageType::AVIF]
+        );
+    }
+
+    #[test]
+    fn test_responsive_snippet() {
+        let output = |path: &str, format: ImageType, width: u32| OutputFile {
+            path: path.to_string(),
+            size: 0,
+            format,
+            quality: None,
+            width: Some(width),
+            height: Some(width / 2),
+        };
+        let outputs = vec![
+            output("a/hero-960w.webp", ImageType::WEBP, 960),
+            output("a/hero-480w.webp", ImageType::WEBP, 480),
+        ];
+        assert_eq!(
+            responsive_snippet(&outputs),
+            "<img src=\"hero-960w.webp\" srcset=\"hero-480w.webp 480w, hero-960w.webp 960w\" sizes=\"100vw\" width=\"960\" height=\"480\" alt=\"\">"
+        );
+
+        let outputs = vec![
+            output("a/my hero-960w.avif", ImageType::AVIF, 960),
+            output("a/my hero-960w.jpg", ImageType::JPEG, 960),
+        ];
+        assert_eq!(
+            responsive_snippet(&outputs),
+            "<picture>\n  <source type=\"image/avif\" srcset=\"my%20hero-960w.avif 960w\" sizes=\"100vw\">\n  <img src=\"my%20hero-960w.jpg\" srcset=\"my%20hero-960w.jpg 960w\" sizes=\"100vw\" width=\"960\" height=\"480\" alt=\"\">\n</picture>"
+        );
+    }
+
+    #[test]
+    fn test_write_output() {
+        let dir = std::env::temp_dir().join(format!("alic-write-{}", std::process::id()));
+        let out_path = dir.join("nested/out.webp");
+        let out_path = out_path.to_str().unwrap();
+        let parameters = settings::ProfileData::new();
+        let now = SystemTime::now();
+
+        write_output(out_path, b"first", "in.png", &parameters, now, now)
+            .ok()
+            .unwrap();
+        assert_eq!(fs::read(out_path).unwrap(), b"first");
+        let entries: Vec<_> = fs::read_dir(dir.join("nested")).unwrap().collect();
+        assert_eq!(entries.len(), 1, "temporary files are cleaned up");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repacks_rotated_jpeg_into_jxl() {
+        use exif::experimental::Writer;
+        use exif::{Field, In, Tag, Value};
+        use image::{Rgb, RgbImage};
+        use img_parts::{Bytes, ImageEXIF};
+        use jpegxl_rs::decode::Data;
+
+        // A JPEG shown turned a quarter by its orientation flag, with GPS
+        let mut exif = std::io::Cursor::new(Vec::new());
+        let mut writer = Writer::new();
+        let fields = [
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![6]),
+            },
+            Field {
+                tag: Tag::GPSLatitudeRef,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"N".to_vec()]),
+            },
+        ];
+        for field in &fields {
+            writer.push_field(field);
+        }
+        writer.write(&mut exif, false).unwrap();
+        let mut pixels = Vec::new();
+        RgbImage::from_fn(64, 32, |x, y| Rgb([(x * 4) as u8, (y * 8) as u8, 128]))
+            .write_to(&mut std::io::Cursor::new(&mut pixels), ImageFormat::Jpeg)
+            .unwrap();
+        let mut jpeg = img_parts::jpeg::Jpeg::from_bytes(Bytes::from(pixels)).unwrap();
+        jpeg.set_exif(Some(Bytes::from(exif.into_inner())));
+        let original = jpeg.encoder().bytes().to_vec();
+
+        let dir = std::env::temp_dir().join(format!("alic-repack-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("photo.jpg");
+        fs::write(&path, &original).unwrap();
+        let mut parameters = settings::ProfileData::new();
+        parameters.should_convert = true;
+        parameters.convert_extension = ImageType::JXL;
+        parameters.color_profile = settings::ColorProfilePolicy::ConvertToSrgb;
+        parameters.should_cache = false;
+        parameters.not_smaller = settings::NotSmallerPolicy::Write;
+        let rebuild = |parameters: &settings::ProfileData, path: &Path| {
+            let result = process_path(
+                parameters.clone(),
+                path.to_string_lossy().to_string(),
+                None,
+                &Job::untracked(),
+            )
+            .ok()
+            .unwrap();
+            let jxl = fs::read(&result.out_path).unwrap();
+            let decoder = jpegxl_rs::decoder_builder().build().unwrap();
+            match decoder.reconstruct(&jxl).unwrap() {
+                (_, Data::Jpeg(rebuilt)) => rebuilt,
+                _ => panic!("JPEG XL has no JPEG to rebuild"),
+            }
+        };
+        assert!(rebuild(&parameters, &path) == original);
+
+        // Stripped metadata doesn't ride along in the repacked JPEG
+        let path = dir.join("stripped.jpg");
+        fs::write(&path, &original).unwrap();
+        parameters.metadata = settings::MetadataPolicy::none();
+        let rebuilt = rebuild(&parameters, &path);
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(&rebuilt))
+            .unwrap();
+        let tags: Vec<Tag> = exif.fields().map(|field| field.tag).collect();
+        assert_eq!(tags, [Tag::Orientation]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_saves_enough() {
+        let mut parameters = settings::ProfileData::new();
+        assert!(saves_enough(950, 1000, &parameters));
+        assert!(!saves_enough(951, 1000, &parameters));
+        parameters.min_savings = 0;
+        assert!(saves_enough(1000, 1000, &parameters));
+        assert!(!saves_enough(1001, 1000, &parameters));
+        parameters.min_savings = 100;
+        parameters.min_savings_unit = settings::SavingsUnit::Bytes;
+        assert!(saves_enough(900, 1000, &parameters));
+        assert!(!saves_enough(901, 1000, &parameters));
+    }
+
+    #[test]
+    fn keeps_metadata_outside_metadata_writer() {
+        let mut source = Vec::new();
+        image::RgbImage::new(8, 8)
+            .write_to(&mut std::io::Cursor::new(&mut source), ImageFormat::Png)
+            .unwrap();
+        let exif = b"MM\0\x2a\0\0\0\x08\0\0\0\0\0\0";
+        let parameters = settings::ProfileData::new();
+        let avif = compress_avif(&source, &parameters, Some(exif), &Job::untracked()).unwrap();
+        assert!(avif.windows(exif.len()).any(|window| window == exif));
+
+        // libcaesium can only keep all of it
+        assert!(caesium_keeps_metadata(&parameters, &ImageType::TIFF));
+        assert!(caesium_keeps_metadata(&parameters, &ImageType::GIF));
+        assert!(!caesium_keeps_metadata(&parameters, &ImageType::JPEG));
+        let mut parameters = parameters;
+        parameters.metadata.gps = false;
+        assert!(!caesium_keeps_metadata(&parameters, &ImageType::TIFF));
+    }
+
+    #[test]
+    fn test_drop_redundant_copies() {
+        let output = |format: ImageType, is_original: bool, out_path: &str| {
+            let output = EncodedOutput {
+                suffix: String::new(),
+                data: Vec::new(),
+                quality: None,
+                format,
+                dimensions: None,
+                is_original,
+            };
+            (output, out_path.to_string())
+        };
+        // The WebP missed the minimum and became a JPEG copy, which mustn't
+        // push out the JPEG that was encoded
+        let kept = drop_redundant_copies(
+            vec![
+                output(ImageType::JPEG, true, "/out/a.min.jpg"),
+                output(ImageType::JPEG, false, "/out/a.min.jpg"),
+                output(ImageType::JPEG, true, "/out/b.min.jpg"),
+                output(ImageType::JPEG, true, "/out/b.min.jpg"),
+            ],
+            "/in/a.jpg",
+        );
+        let kept: Vec<(bool, &str)> = kept
+            .iter()
+            .map(|(output, out_path)| (output.is_original, out_path.as_str()))
+            .collect();
+        assert_eq!(kept, [(false, "/out/a.min.jpg"), (true, "/out/b.min.jpg")]);
+    }
+
+    #[test]
+    fn keeps_conversions_that_save_too_little() {
+        use image::{Rgb, RgbImage};
+
+        let dir = std::env::temp_dir().join(format!("alic-convert-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("flat.png");
+        RgbImage::from_pixel(16, 16, Rgb([40, 80, 120]))
+            .save_with_format(&path, ImageFormat::Png)
+            .unwrap();
+        let mut parameters = settings::ProfileData::new();
+        parameters.should_convert = true;
+        parameters.convert_extension = ImageType::WEBP;
+        // Encoded with libwebp, and no output can save all of its original
+        parameters.webp_method = 6;
+        parameters.min_savings = 100;
+        parameters.should_cache = false;
+
+        let result = process_path(
+            parameters,
+            path.to_string_lossy().to_string(),
+            None,
+            &Job::untracked(),
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(result.outputs[0].format, ImageType::WEBP);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_numbered_path() {
+        assert_eq!(numbered_path("a/name.min.webp", 2), "a/name.min (2).webp");
+        assert_eq!(numbered_path("a/name", 3), "a/name (3)");
+    }
+
+    #[test]
+    fn test_claim_out_paths() {
+        use settings::CollisionPolicy;
+        let out_path = "/nonexistent/claim/a.min.webp".to_string();
+
+        let claimed = claim_out_paths(vec![out_path.clone()], "a.png", &CollisionPolicy::Replace);
+        assert_eq!(claimed.ok(), Some(vec![Some(out_path.clone())]));
+
+        // The same input may claim its own output again
+        let claimed = claim_out_paths(vec![out_path.clone()], "a.png", &CollisionPolicy::Error);
+        assert_eq!(claimed.ok(), Some(vec![Some(out_path.clone())]));
+
+        let claimed = claim_out_paths(vec![out_path.clone()], "a.jpg", &CollisionPolicy::Replace);
+        assert!(matches!(
+            claimed,
+            Err(AlicError {
+                error_type: AlicErrorType::OutputExists,
+                ..
+            })
+        ));
+
+        let claimed = claim_out_paths(vec![out_path.clone()], "a.jpg", &CollisionPolicy::Skip);
+        assert_eq!(claimed.ok(), Some(vec![None]));
+
+        let claimed = claim_out_paths(vec![out_path.clone()], "a.jpg", &CollisionPolicy::Rename);
+        assert_eq!(
+            claimed.ok(),
+            Some(vec![Some("/nonexistent/claim/a.min (2).webp".to_string())])
+        );
+        let claimed = claim_out_paths(vec![out_path], "a.gif", &CollisionPolicy::Rename);
+        assert_eq!(
+            claimed.ok(),
+            Some(vec![Some("/nonexistent/claim/a.min (3).webp".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_templated_out_path() {
+        let mut parameters = settings::ProfileData::new();
+        parameters.should_convert = true;
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 3, 9)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let naming = Naming {
+            parameters: &parameters,
+            path: "shop/product.jpg",
+            root: None,
+            guessed_format: &ImageType::JPEG,
+            template: Some(Template::parse("{name}_{width}x{height}_q{quality}.{ext}").unwrap()),
+            date,
+            taken: date,
+        };
+        let encoded = Encoded {
+            data: &[],
+            width: Some(1200),
+            height: Some(800),
+            quality: Some(75),
+        };
+
+        assert_eq!(naming.out_path(&ImageType::WEBP, "", None), None);
+        assert_eq!(
+            naming.out_path(&ImageType::WEBP, "", Some(&encoded)),
+            Some("shop/product_1200x800_q75.webp".to_string())
+        );
+        assert_eq!(
+            naming.out_path(&ImageType::WEBP, "-480w", Some(&encoded)),
+            Some("shop/product_1200x800_q75-480w.webp".to_string())
+        );
+    }
+
     #[test]
     fn test_get_out_path() {
         let mut parameters = settings::ProfileData::new();
-        let mut result = get_out_path(&parameters, "test/test.png", &ImageType::PNG);
+        let mut result = get_out_path(
+            &parameters,
+            "test/test.png",
+            None,
+            &ImageType::PNG,
+            &ImageType::PNG,
+            "",
+        );
         assert_eq!(result, "test/test.min.png".to_string());
 
         parameters = settings::ProfileData::new();
-        result = get_out_path(&parameters, "test/test.jpeg", &ImageType::JPEG);
+        result = get_out_path(
+            &parameters,
+            "test/test.jpeg",
+            None,
+            &ImageType::JPEG,
+            &ImageType::JPEG,
+            "",
+        );
         assert_eq!(result, "test/test.min.jpeg".to_string());
 
         parameters = settings::ProfileData::new();
-        result = get_out_path(&parameters, "test/test.jpg", &ImageType::JPEG);
+        result = get_out_path(
+            &parameters,
+            "test/test.jpg",
+            None,
+            &ImageType::JPEG,
+            &ImageType::JPEG,
+            "",
+        );
         assert_eq!(result, "test/test.min.jpg".to_string());
 
         parameters = settings::ProfileData::new();
         parameters.should_convert = true;
         parameters.convert_extension = ImageType::PNG;
-        result = get_out_path(&parameters, "test/test.jpeg", &ImageType::JPEG);
+        result = get_out_path(
+            &parameters,
+            "test/test.jpeg",
+            None,
+            &ImageType::JPEG,
+            &ImageType::PNG,
+            "",
+        );
         assert_eq!(result, "test/test.min.png".to_string());
 
         parameters = settings::ProfileData::new();
         parameters.should_convert = false;
         parameters.convert_extension = ImageType::PNG;
-        result = get_out_path(&parameters, "test/test.jpeg", &ImageType::JPEG);
+        result = get_out_path(
+            &parameters,
+            "test/test.jpeg",
+            None,
+            &ImageType::JPEG,
+            &ImageType::JPEG,
+            "",
+        );
         assert_eq!(result, "test/test.min.jpeg".to_string());
 
+        // HEIC can't be written, so it becomes JPEG even without converting
+        parameters = settings::ProfileData::new();
+        result = get_out_path(
+            &parameters,
+            "test/IMG_0001.HEIC",
+            None,
+            &ImageType::HEIC,
+            &target_formats(&parameters, &ImageType::HEIC)[0],
+            "",
+        );
+        assert_eq!(result, "test/IMG_0001.min.jpg".to_string());
+
         parameters = settings::ProfileData::new();
         parameters.add_postfix = false;
-        result = get_out_path(&parameters, "test/test.jpeg", &ImageType::PNG);
+        result = get_out_path(
+            &parameters,
+            "test/test.jpeg",
+            None,
+            &ImageType::PNG,
+            &ImageType::PNG,
+            "",
+        );
         assert_eq!(result, "test/test.png".to_string());
 
         parameters = settings::ProfileData::new();
         parameters.postfix = ".bong".to_string();
-        result = get_out_path(&parameters, "test/test.jpeg", &ImageType::PNG);
+        result = get_out_path(
+            &parameters,
+            "test/test.jpeg",
+            None,
+            &ImageType::PNG,
+            &ImageType::PNG,
+            "",
+        );
         assert_eq!(result, "test/test.bong.png".to_string());
+
+        parameters = settings::ProfileData::new();
+        parameters.should_convert = true;
+        result = get_out_path(
+            &parameters,
+            "test/test.jpeg",
+            None,
+            &ImageType::JPEG,
+            &ImageType::WEBP,
+            "-480w",
+        );
+        assert_eq!(result, "test/test-480w.min.webp".to_string());
+
+        parameters = settings::ProfileData::new();
+        parameters.should_output_dir = true;
+        parameters.output_dir = "/out".to_string();
+        result = get_out_path(
+            &parameters,
+            "/in/a/b/test.png",
+            Some("/in"),
+            &ImageType::PNG,
+            &ImageType::PNG,
+            "",
+        );
+        assert_eq!(result, "/out/a/b/test.min.png".to_string());
+
+        result = get_out_path(
+            &parameters,
+            "/in/a/b/test.png",
+            None,
+            &ImageType::PNG,
+            &ImageType::PNG,
+            "",
+        );
+        assert_eq!(result, "/out/test.min.png".to_string());
+
+        parameters.flatten_output = true;
+        result = get_out_path(
+            &parameters,
+            "/in/a/b/test.png",
+            Some("/in"),
+            &ImageType::PNG,
+            &ImageType::PNG,
+            "",
+        );
+        assert_eq!(result, "/out/test.min.png".to_string());
     }
 }
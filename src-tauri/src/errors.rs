@@ -1,13 +1,13 @@
 use specta::Type;
 
-#[derive(serde::Serialize, serde::Deserialize, Type)]
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct AlicError {
     pub error: String,
     pub error_type: AlicErrorType,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Type)]
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
 pub enum AlicErrorType {
     Unknown,
     FileTooLarge,
@@ -17,4 +17,8 @@ pub enum AlicErrorType {
     NotSmaller,
     ImageResizeError,
     InvalidHexColor,
+    TargetSizeNotMet,
+    OutputExists,
+    Skipped,
+    Cancelled,
 }
@@ -2,8 +2,15 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 use tauri_specta::Event;
 
+use crate::compress::CompressResult;
+use crate::errors::AlicError;
+
+/// A file to queue, with the folder it was found in when a folder was added.
 #[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
-pub struct AddFileEvent(pub String);
+pub struct AddFileEvent {
+    pub path: String,
+    pub root: Option<String>,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
 pub struct ClearFilesEvent;
@@ -40,3 +47,40 @@ pub enum UpdateStateEvent {
         release_notes: Option<String>,
     },
 }
+
+/// A compression job moving on to another stage.
+#[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgressEvent {
+    pub job_id: u32,
+    pub stage: JobStage,
+    // Since the job started
+    pub elapsed_ms: u32,
+    // Spent in the stage before this one
+    pub previous_stage_ms: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Type)]
+#[serde(tag = "type")]
+pub enum JobStage {
+    // Received, waiting for its turn
+    Queued,
+    Reading,
+    // Orienting, resizing and converting colors
+    Resizing,
+    // Which output is being encoded, counting from 1, out of how many
+    Encoding { output: u32, outputs: u32 },
+    Writing,
+    // Finished, failed or cancelled. The command's result tells which.
+    Done,
+}
+
+/// A queued file done processing, with its result or error.
+#[derive(Serialize, Deserialize, Debug, Clone, Type, Event)]
+#[serde(rename_all = "camelCase")]
+pub struct JobResultEvent {
+    pub job_id: u32,
+    pub path: String,
+    pub result: Option<CompressResult>,
+    pub error: Option<AlicError>,
+}
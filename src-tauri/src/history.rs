@@ -0,0 +1,297 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::debug;
+use specta::Type;
+
+use crate::app_dirs;
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+static HISTORY: LazyLock<Mutex<History>> =
+    LazyLock::new(|| Mutex::new(History::open(app_dirs::data_file(HISTORY_FILE))));
+
+/// A file moved to the trash to make room for an output.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedFile {
+    // The batch it was trashed in, numbered by when the batch started
+    pub batch: u32,
+    // The image that was being compressed
+    pub original_path: String,
+    // Where the trashed file was, now taken by the output
+    pub output_path: String,
+    pub trashed_path: String,
+    // Seconds since 1970
+    pub trashed_at: u32,
+}
+
+/// The files trashed in one batch that haven't been restored, oldest first.
+#[derive(serde::Serialize, Type, Debug, Clone, PartialEq)]
+pub struct HistoryBatch {
+    pub id: u32,
+    pub files: Vec<TrashedFile>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+enum Entry {
+    Trashed(TrashedFile),
+    Restored { trashed_path: String },
+}
+
+/// Files trashed by past batches, kept in an append-only JSON Lines file
+/// like the cache so they can be put back after an unwanted overwrite.
+pub struct History {
+    path: Option<PathBuf>,
+    // Not yet restored, oldest first
+    files: Vec<TrashedFile>,
+    // The batch files are trashed in now, started by the first of them
+    batch: Option<u32>,
+    last_batch: u32,
+    // The file ends in a line cut short by a crash
+    torn: bool,
+}
+
+impl History {
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let contents = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let mut files: Vec<TrashedFile> = Vec::new();
+        // A line cut short by a crash is skipped, not fatal
+        for entry in contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+        {
+            match entry {
+                Entry::Trashed(file) => files.push(file),
+                Entry::Restored { trashed_path } => {
+                    files.retain(|file| file.trashed_path != trashed_path)
+                }
+            }
+        }
+        let last_batch = files.iter().map(|file| file.batch).max().unwrap_or(0);
+        let torn = !contents.is_empty() && !contents.ends_with('\n');
+        Self {
+            path,
+            files,
+            batch: None,
+            last_batch,
+            torn,
+        }
+    }
+
+    pub fn record(&mut self, original_path: &str, output_path: &str, trashed_path: String) {
+        let now = now();
+        let batch = *self.batch.get_or_insert_with(|| {
+            // Batches started in the same second still get their own number
+            self.last_batch = now.max(self.last_batch + 1);
+            self.last_batch
+        });
+        let file = TrashedFile {
+            batch,
+            original_path: original_path.to_string(),
+            output_path: output_path.to_string(),
+            trashed_path,
+            trashed_at: now,
+        };
+        self.append(&Entry::Trashed(file.clone()));
+        self.files.push(file);
+    }
+
+    pub fn new_batch(&mut self) {
+        self.batch = None;
+    }
+
+    /// The `limit` most recent batches, newest first.
+    pub fn batches(&self, limit: usize) -> Vec<HistoryBatch> {
+        let mut batches: Vec<HistoryBatch> = Vec::new();
+        for file in self.files.iter().rev() {
+            match batches.iter().position(|batch| batch.id == file.batch) {
+                Some(index) => batches[index].files.insert(0, file.clone()),
+                None if batches.len() < limit => batches.push(HistoryBatch {
+                    id: file.batch,
+                    files: vec![file.clone()],
+                }),
+                None => {}
+            }
+        }
+        batches.sort_by_key(|batch| std::cmp::Reverse(batch.id));
+        batches
+    }
+
+    /// Put the trashed files `which` picks back where they were, replacing
+    /// their outputs, and return their paths. The newest go back first, so a
+    /// path trashed twice ends up with the oldest file.
+    pub fn restore(&mut self, which: impl Fn(&TrashedFile) -> bool) -> Result<Vec<String>, String> {
+        let picked: Vec<TrashedFile> = self
+            .files
+            .iter()
+            .rev()
+            .filter(|f| which(f))
+            .cloned()
+            .collect();
+        if picked.is_empty() {
+            return Err("Nothing to restore".to_string());
+        }
+        let mut restored = Vec::new();
+        let mut errors = Vec::new();
+        for file in picked {
+            match put_back(&file) {
+                Ok(()) => {
+                    self.append(&Entry::Restored {
+                        trashed_path: file.trashed_path.clone(),
+                    });
+                    self.files.retain(|f| f.trashed_path != file.trashed_path);
+                    restored.push(file.output_path);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(restored),
+            false => Err(errors.join("\n")),
+        }
+    }
+
+    fn append(&mut self, entry: &Entry) {
+        if let Some(path) = &self.path {
+            match append(path, entry, self.torn) {
+                Ok(()) => self.torn = false,
+                Err(e) => debug!("Could not write history entry: {e}"),
+            }
+        }
+    }
+}
+
+fn append(path: &Path, entry: &Entry, torn: bool) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut line = match torn {
+        true => "\n".to_string(),
+        false => String::new(),
+    };
+    line.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| e.to_string())
+}
+
+/// Move `file` out of the trash to its old path. Renaming over the output
+/// removes it in the same step.
+fn put_back(file: &TrashedFile) -> Result<(), String> {
+    let trashed = Path::new(&file.trashed_path);
+    if !trashed.exists() {
+        return Err(format!("{} is no longer in the trash", file.output_path));
+    }
+    let path = Path::new(&file.output_path);
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    // The trash can be on another volume, where renaming doesn't work
+    fs::rename(trashed, path)
+        .or_else(|_| fs::copy(trashed, path).and_then(|_| fs::remove_file(trashed)))
+        .map_err(|e| format!("Could not restore {}: {e}", file.output_path))
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as u32)
+}
+
+/// Remember that `trashed_path` is what `write_output` moved away from
+/// `output_path` while compressing `original_path`.
+pub fn record(original_path: &str, output_path: &str, trashed_path: String) {
+    HISTORY
+        .lock()
+        .unwrap()
+        .record(original_path, output_path, trashed_path);
+}
+
+/// Trash the files that come next into a new batch.
+pub fn new_batch() {
+    HISTORY.lock().unwrap().new_batch();
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_history(limit: u32) -> Vec<HistoryBatch> {
+    HISTORY.lock().unwrap().batches(limit as usize)
+}
+
+/// Restore every file trashed in `batch`.
+#[tauri::command]
+#[specta::specta]
+pub fn restore_batch(batch: u32) -> Result<Vec<String>, String> {
+    HISTORY.lock().unwrap().restore(|file| file.batch == batch)
+}
+
+/// Restore single files, by where they are in the trash.
+#[tauri::command]
+#[specta::specta]
+pub fn restore_files(trashed_paths: Vec<String>) -> Result<Vec<String>, String> {
+    HISTORY
+        .lock()
+        .unwrap()
+        .restore(|file| trashed_paths.contains(&file.trashed_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_trashed_files() {
+        let dir = std::env::temp_dir().join(format!("alic-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let log = dir.join("history.jsonl");
+
+        let mut history = History::open(Some(log.clone()));
+        for (name, contents) in [("a.png", "output a"), ("b.png", "output b")] {
+            fs::write(path(name), contents).unwrap();
+            fs::write(path(&format!("trash-{name}")), "original").unwrap();
+            history.record(&path(name), &path(name), path(&format!("trash-{name}")));
+        }
+        history.new_batch();
+        fs::write(path("trash-a-again.png"), "output a").unwrap();
+        history.record(&path("a.png"), &path("a.png"), path("trash-a-again.png"));
+
+        let mut history = History::open(Some(log.clone()));
+        let batches = history.batches(10);
+        assert_eq!(batches.len(), 2);
+        assert!(batches[0].id > batches[1].id);
+        assert_eq!(batches[1].files.len(), 2);
+        assert_eq!(history.batches(1), batches[..1]);
+
+        // A single file, then the rest of its batch
+        assert_eq!(
+            history.restore(|file| file.trashed_path == path("trash-b.png")),
+            Ok(vec![path("b.png")])
+        );
+        assert_eq!(fs::read_to_string(path("b.png")).unwrap(), "original");
+        let first = batches[1].id;
+        assert_eq!(
+            history.restore(|file| file.batch == first),
+            Ok(vec![path("a.png")])
+        );
+        assert_eq!(fs::read_to_string(path("a.png")).unwrap(), "original");
+        assert!(!Path::new(&path("trash-a.png")).exists());
+
+        let mut history = History::open(Some(log));
+        assert_eq!(history.batches(10).len(), 1);
+        assert!(history.restore(|file| file.batch == first).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Instant;
+
+use tauri_specta::Event;
+
+use crate::errors::{AlicError, AlicErrorType};
+use crate::events::{JobProgressEvent, JobStage};
+
+/// Cancellation flags of the jobs the frontend has started, by job ID.
+static JOBS: LazyLock<Mutex<HashMap<u32, Arc<AtomicBool>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// A running compression, checked for cancellation between pipeline stages.
+pub struct Job {
+    id: Option<u32>,
+    cancelled: Arc<AtomicBool>,
+    app: Option<tauri::AppHandle>,
+    // How many images the job's pool is running, this one included
+    images_running: Option<Arc<AtomicUsize>>,
+    started: Instant,
+    stage_started: Cell<Instant>,
+}
+
+impl Job {
+    /// Register job `id` so `cancel` can reach it until it's dropped.
+    pub fn start(id: u32) -> Self {
+        let cancelled = Arc::<AtomicBool>::default();
+        JOBS.lock().unwrap().insert(id, cancelled.clone());
+        Job {
+            id: Some(id),
+            cancelled,
+            app: None,
+            images_running: None,
+            started: Instant::now(),
+            stage_started: Cell::new(Instant::now()),
+        }
+    }
+
+    /// A job nothing can cancel, for the CLI.
+    pub fn untracked() -> Self {
+        Job {
+            id: None,
+            cancelled: Arc::default(),
+            app: None,
+            images_running: None,
+            started: Instant::now(),
+            stage_started: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Emit a `JobProgressEvent` to `app` at every stage, starting now.
+    pub fn reporting_to(mut self, app: tauri::AppHandle) -> Self {
+        self.app = Some(app);
+        self.stage(JobStage::Queued);
+        self
+    }
+
+    /// Share the CPUs with the other images counted in `images_running`.
+    pub fn in_pool(mut self, images_running: Arc<AtomicUsize>) -> Self {
+        self.images_running = Some(images_running);
+        self
+    }
+
+    pub fn images_running(&self) -> usize {
+        self.images_running
+            .as_ref()
+            .map_or(1, |running| running.load(Ordering::Relaxed))
+    }
+
+    pub fn stage(&self, stage: JobStage) {
+        let now = Instant::now();
+        let previous = self.stage_started.replace(now);
+        let (Some(id), Some(app)) = (self.id, &self.app) else {
+            return;
+        };
+        let millis = |since: Instant| now.duration_since(since).as_millis() as u32;
+        let _ = JobProgressEvent {
+            job_id: id,
+            stage,
+            elapsed_ms: millis(self.started),
+            previous_stage_ms: millis(previous),
+        }
+        .emit(app);
+    }
+
+    pub fn check(&self) -> Result<(), AlicError> {
+        match self.cancelled.load(Ordering::Relaxed) {
+            true => Err(AlicError {
+                error: "Cancelled.".to_string(),
+                error_type: AlicErrorType::Cancelled,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.stage(JobStage::Done);
+        if let Some(id) = self.id {
+            JOBS.lock().unwrap().remove(&id);
+        }
+    }
+}
+
+/// Cancel job `job_id` if it's running, returning whether it was.
+pub fn cancel(job_id: u32) -> bool {
+    match JOBS.lock().unwrap().get(&job_id) {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancels_running_jobs() {
+        let job = Job::start(1);
+        assert!(job.check().is_ok());
+        assert!(cancel(1));
+        assert!(job.check().is_err());
+        drop(job);
+        // Dropping forgets the job, so its ID starts fresh
+        assert!(!cancel(1));
+        assert!(Job::start(1).check().is_ok());
+
+        // Unknown IDs aren't remembered for later
+        assert!(!cancel(2));
+        assert!(Job::start(2).check().is_ok());
+        assert!(Job::untracked().check().is_ok());
+    }
+}
@@ -1,10 +1,23 @@
 pub(crate) mod cli;
+mod animation;
+mod app_dirs;
+mod avis;
+mod cache;
+mod color;
 pub mod compress;
 pub(crate) mod errors;
 mod events;
+mod history;
+mod jobs;
 mod macos;
+mod marker;
+mod metadata;
+mod queue;
+mod report;
 mod resize;
 pub mod settings;
+mod ssim;
+mod template;
 mod update;
 
 use events::{AddFileEvent, ClearFilesEvent, ErrorEvent, OpenAddFileDialogEvent, UpdateStateEvent};
@@ -108,7 +121,9 @@ fn save_clipboard_image(app: &tauri::AppHandle) -> Result<(), String> {
     image
         .save(&path)
         .map_err(|e| format!("Could not save image to {path}: {e}"))?;
-    AddFileEvent(path).emit(app).map_err(|e| e.to_string())
+    AddFileEvent { path, root: None }
+        .emit(app)
+        .map_err(|e| e.to_string())
 }
 
 fn specta_builder() -> Builder<tauri::Wry> {
@@ -116,9 +131,21 @@ fn specta_builder() -> Builder<tauri::Wry> {
         .commands(collect_commands![
             open_settings_window,
             open_link_in_browser,
-            compress::process_img,
             compress::get_file_info,
             compress::get_all_images,
+            compress::clear_batch,
+            queue::enqueue_file,
+            queue::get_queue,
+            queue::start_queue,
+            queue::set_queue_paused,
+            queue::set_job_priority,
+            queue::retry_job,
+            queue::cancel_job,
+            queue::clear_queue,
+            history::get_history,
+            history::restore_batch,
+            history::restore_files,
+            report::export_report,
             settings::get_settings,
             settings::save_settings,
             settings::reset_settings,
@@ -139,7 +166,11 @@ fn specta_builder() -> Builder<tauri::Wry> {
             events::SettingsChangedEvent,
             events::OpenAddFileDialogEvent,
             events::UpdateStateEvent,
+            events::JobProgressEvent,
+            events::JobResultEvent,
         ])
+        // Queued files come back through events, so no command names it
+        .typ::<compress::FileEntry>()
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -308,6 +339,7 @@ pub fn run() {
             builder.mount_events(app);
             // Store setup
             app.store("settings.json")?;
+            app_dirs::init(app.handle());
 
             // Handle CLI args — if --input was provided, process and exit
             if let Ok(matches) = app.cli().matches() {
@@ -317,6 +349,10 @@ pub fn run() {
             // After CLI handling so CLI runs keep using the last active
             // profile rather than the startup default
             settings::activate_startup_profile(app.handle());
+            let threads = settings::get_settings_data(app.handle())
+                .map(|(settings, _)| settings.threads)
+                .unwrap_or_default();
+            queue::start(app.handle(), threads);
 
             Ok(())
         })
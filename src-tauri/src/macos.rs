@@ -1,6 +1,10 @@
 use log::warn;
 use objc2_app_kit::{NSApp, NSColor, NSColorSpace, NSRequestUserAttentionType};
+use objc2_core_foundation::{CFData, CFDictionary, CFMutableData, CFNumber, CFString};
 use objc2_foundation::{MainThreadMarker, NSFileManager, NSString, NSURL};
+use objc2_image_io::{
+    CGImageDestination, CGImageSource, kCGImageDestinationLossyCompressionQuality,
+};
 use tauri_plugin_shell::ShellExt;
 
 #[tauri::command]
@@ -29,16 +33,49 @@ pub async fn get_cpu_count() -> i32 {
     crate::compress::num_cpus() as i32
 }
 
-pub fn trash_file(file_path: &str) -> Result<(), String> {
+/// Move `file_path` to the trash, returning where it ended up there.
+pub fn trash_file(file_path: &str) -> Result<String, String> {
     let url = NSURL::fileURLWithPath(&NSString::from_str(file_path));
-    let result = NSFileManager::defaultManager().trashItemAtURL_resultingItemURL_error(&url, None);
+    let mut trashed = None;
+    let result = NSFileManager::defaultManager()
+        .trashItemAtURL_resultingItemURL_error(&url, Some(&mut trashed));
 
     if result.is_err() {
         let err = result.err().unwrap();
         // println!("Failed to move file to Trash: {:?}", err);
         return Err(err.to_string());
     }
-    Ok(())
+    trashed
+        .and_then(|url| url.path())
+        .map(|path| path.to_string())
+        .ok_or_else(|| format!("Moved {file_path} to the trash, but not sure where"))
+}
+
+/// Decode a HEIF image into a full quality JPEG. ImageIO copies the EXIF
+/// (orientation, capture date, GPS) across without rotating the pixels.
+pub fn heif_to_jpeg(data: &[u8]) -> Result<Vec<u8>, String> {
+    unsafe {
+        let source = CGImageSource::with_data(&CFData::from_bytes(data), None)
+            .filter(|source| source.count() > 0)
+            .ok_or("Failed to read HEIF image")?;
+        let output = CFMutableData::new(None, 0).ok_or("Failed to allocate JPEG buffer")?;
+        let destination = CGImageDestination::with_data(
+            &output,
+            &CFString::from_static_str("public.jpeg"),
+            1,
+            None,
+        )
+        .ok_or("Failed to create JPEG encoder")?;
+        let options = CFDictionary::from_slices(
+            &[kCGImageDestinationLossyCompressionQuality],
+            &[&*CFNumber::new_f64(1.0)],
+        );
+        destination.add_image_from_source(&source, 0, Some(options.as_opaque()));
+        if !destination.finalize() {
+            return Err("Failed to encode HEIF image as JPEG".to_string());
+        }
+        Ok(output.to_vec())
+    }
 }
 
 #[tauri::command]
@@ -0,0 +1,160 @@
+use crate::compress::ImageType;
+
+/// Text embedded in outputs so a later run can tell they came from Alic.
+const MARKER: &[u8] = b"Alic Image Compressor";
+const PNG_SIGNATURE_LEN: usize = 8;
+
+/// Tag `data` as an Alic output. PNG gets a `Software` text chunk and JPEG
+/// a comment segment; other formats are returned as they are.
+pub fn add_marker(data: Vec<u8>, format: &ImageType) -> Vec<u8> {
+    if has_marker(&data, format) {
+        return data;
+    }
+    match format {
+        ImageType::PNG => add_png_marker(data),
+        ImageType::JPEG => add_jpeg_marker(data),
+        _ => data,
+    }
+}
+
+/// How many bytes `add_marker` adds to an unmarked image.
+pub fn marker_len(format: &ImageType) -> usize {
+    match format {
+        // Length, type and CRC around the text
+        ImageType::PNG => png_marker_text().len() + 12,
+        // Marker and length before the text
+        ImageType::JPEG => MARKER.len() + 4,
+        _ => 0,
+    }
+}
+
+pub fn has_marker(data: &[u8], format: &ImageType) -> bool {
+    match format {
+        ImageType::PNG => png_chunks(data)
+            .any(|(kind, body)| kind == b"tEXt" && body == png_marker_text().as_slice()),
+        ImageType::JPEG => {
+            jpeg_segments(data).any(|(marker, body)| marker == 0xFE && body == MARKER)
+        }
+        _ => false,
+    }
+}
+
+fn png_marker_text() -> Vec<u8> {
+    [b"Software\0".as_slice(), MARKER].concat()
+}
+
+fn add_png_marker(data: Vec<u8>) -> Vec<u8> {
+    // The text chunk goes right after IHDR, which must come first
+    let Some((b"IHDR", ihdr)) = png_chunks(&data).next() else {
+        return data;
+    };
+    let insert_at = PNG_SIGNATURE_LEN + 12 + ihdr.len();
+    let text = png_marker_text();
+    let mut chunk = Vec::with_capacity(text.len() + 12);
+    chunk.extend_from_slice(&(text.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(b"tEXt");
+    chunk.extend_from_slice(&text);
+    chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+    [&data[..insert_at], &chunk, &data[insert_at..]].concat()
+}
+
+/// Chunk types and bodies up to the image data.
+pub(crate) fn png_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut offset = PNG_SIGNATURE_LEN;
+    std::iter::from_fn(move || {
+        let length = u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let kind = data.get(offset + 4..offset + 8)?;
+        let body = data.get(offset + 8..offset + 8 + length)?;
+        if kind == b"IDAT" {
+            return None;
+        }
+        offset += length + 12;
+        Some((kind, body))
+    })
+}
+
+fn add_jpeg_marker(data: Vec<u8>) -> Vec<u8> {
+    // After the APPn segments, which readers expect straight after SOI
+    let mut insert_at = 2;
+    for (marker, body) in jpeg_segments(&data) {
+        if !(0xE0..=0xEF).contains(&marker) {
+            break;
+        }
+        insert_at += body.len() + 4;
+    }
+    let mut segment = vec![0xFF, 0xFE];
+    segment.extend_from_slice(&((MARKER.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(MARKER);
+    [&data[..insert_at], &segment, &data[insert_at..]].concat()
+}
+
+/// Segment markers and bodies up to the start of scan.
+fn jpeg_segments(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut offset = 2;
+    let is_jpeg = data.starts_with(&[0xFF, 0xD8]);
+    std::iter::from_fn(move || {
+        if !is_jpeg || *data.get(offset)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(offset + 1)?;
+        if marker == 0xDA {
+            return None;
+        }
+        let length =
+            u16::from_be_bytes(data.get(offset + 2..offset + 4)?.try_into().ok()?) as usize;
+        let body = data.get(offset + 4..offset + 2 + length)?;
+        offset += length + 2;
+        Some((marker, body))
+    })
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::new(4, 4)
+            .write_to(&mut Cursor::new(&mut data), format)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn marks_png_and_jpeg() {
+        for (format, image_type) in [
+            (ImageFormat::Png, ImageType::PNG),
+            (ImageFormat::Jpeg, ImageType::JPEG),
+        ] {
+            let data = encoded(format);
+            assert!(!has_marker(&data, &image_type));
+            let marked = add_marker(data.clone(), &image_type);
+            assert!(has_marker(&marked, &image_type));
+            assert_eq!(marked.len(), data.len() + marker_len(&image_type));
+            // Still a valid image, and marking twice adds nothing
+            assert!(image::load_from_memory(&marked).is_ok());
+            assert_eq!(add_marker(marked.clone(), &image_type), marked);
+        }
+    }
+
+    #[test]
+    fn png_crc() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+}
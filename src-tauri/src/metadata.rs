@@ -0,0 +1,602 @@
+use std::io::Cursor;
+
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Tag, Value};
+use img_parts::jpeg::{Jpeg, JpegSegment};
+use img_parts::png::{Png, PngChunk};
+use img_parts::{Bytes, ImageEXIF, ImageICC};
+
+use crate::compress::ImageType;
+use crate::settings::MetadataPolicy;
+
+const XMP_JPEG_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const XMP_PNG_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+const IPTC_JPEG_PREFIX: &[u8] = b"Photoshop 3.0\0";
+const WEBP_EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+// VP8X flags
+const WEBP_ICC: u8 = 0x20;
+const WEBP_EXIF: u8 = 0x08;
+const WEBP_XMP: u8 = 0x04;
+
+/// Metadata read from a source image, already narrowed to the kinds a
+/// profile keeps. Encoders only see pixels, so this is written back onto
+/// each output.
+#[derive(Default)]
+pub struct Metadata {
+    // TIFF structure, as EXIF is stored in every format
+    exif: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    // Photoshop resource block, which only JPEG has a place for
+    iptc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Read what `policy` keeps from a JPEG, PNG or WebP. The orientation
+    /// flag is only kept when `keep_orientation` says the pixels weren't
+    /// rotated to match it.
+    pub fn read(data: &[u8], policy: &MetadataPolicy, keep_orientation: bool) -> Self {
+        let mut metadata = match image::guess_format(data) {
+            Ok(image::ImageFormat::Jpeg) => read_jpeg(data),
+            Ok(image::ImageFormat::Png) => read_png(data),
+            Ok(image::ImageFormat::WebP) => read_webp(data),
+            _ => return Self::default(),
+        };
+        metadata.exif = metadata
+            .exif
+            .and_then(|exif| filter_exif(&exif, policy, keep_orientation));
+        if !policy.copyright {
+            metadata.xmp = None;
+            metadata.iptc = None;
+        }
+        metadata.xmp = metadata
+            .xmp
+            .map(|xmp| filter_xmp(&xmp, policy, keep_orientation));
+        metadata
+    }
+
+    /// Whether `write` can put metadata into `format`.
+    pub fn writes(format: &ImageType) -> bool {
+        matches!(format, ImageType::JPEG | ImageType::PNG | ImageType::WEBP)
+    }
+
+    pub fn exif(&self) -> Option<&[u8]> {
+        self.exif.as_deref()
+    }
+
+    pub fn icc(&self) -> Option<&[u8]> {
+        self.icc.as_deref()
+    }
+
+    pub fn strip_icc(&mut self) {
+        self.icc = None;
+    }
+
+    /// Replace the metadata of an encoded JPEG, PNG or WebP with this.
+    /// Other formats are returned as they are, so their encoders have to
+    /// carry it.
+    pub fn write(&self, data: Vec<u8>, format: &ImageType) -> Vec<u8> {
+        let written = match format {
+            ImageType::JPEG => self.write_jpeg(&data),
+            ImageType::PNG => self.write_png(&data),
+            ImageType::WEBP => self.write_webp(&data),
+            _ => None,
+        };
+        written.unwrap_or(data)
+    }
+
+    /// Narrow the metadata of a JPEG about to be repacked into JPEG XL,
+    /// which carries its segments over as they are. A JPEG that keeps
+    /// everything is left untouched, so it can still be rebuilt bit for bit.
+    pub fn narrow_jpeg(data: Vec<u8>, policy: &MetadataPolicy, strip_icc: bool) -> Vec<u8> {
+        if *policy == MetadataPolicy::all() && !strip_icc {
+            return data;
+        }
+        // The pixels aren't rotated, so the orientation flag has to stay
+        let mut metadata = Self::read(&data, policy, true);
+        if strip_icc {
+            metadata.strip_icc();
+        }
+        metadata.write(data, &ImageType::JPEG)
+    }
+
+    fn write_jpeg(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut jpeg = Jpeg::from_bytes(Bytes::copy_from_slice(data)).ok()?;
+        jpeg.set_exif(self.exif.clone().map(Bytes::from));
+        jpeg.set_icc_profile(self.icc.clone().map(Bytes::from));
+        let segments = jpeg.segments_mut();
+        segments.retain(|segment| jpeg_xmp(segment).is_none() && jpeg_iptc(segment).is_none());
+        // After the other APPn segments
+        let at = segments
+            .iter()
+            .position(|segment| !(0xE0..=0xEF).contains(&segment.marker()))
+            .unwrap_or(segments.len());
+        let added = [
+            self.xmp
+                .as_ref()
+                .map(|xmp| (0xE1, [XMP_JPEG_PREFIX, xmp].concat())),
+            self.iptc
+                .as_ref()
+                .map(|iptc| (0xED, [IPTC_JPEG_PREFIX, iptc].concat())),
+        ];
+        segments.splice(
+            at..at,
+            added.into_iter().flatten().map(|(marker, contents)| {
+                JpegSegment::new_with_contents(marker, Bytes::from(contents))
+            }),
+        );
+        Some(jpeg.encoder().bytes().to_vec())
+    }
+
+    fn write_png(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let mut png = Png::from_bytes(Bytes::copy_from_slice(data)).ok()?;
+        png.set_exif(self.exif.clone().map(Bytes::from));
+        png.set_icc_profile(self.icc.clone().map(Bytes::from));
+        let chunks = png.chunks_mut();
+        chunks.retain(|chunk| png_xmp(chunk).is_none());
+        if let Some(xmp) = &self.xmp {
+            // Uncompressed, with empty language and translated keyword
+            let contents = [XMP_PNG_KEYWORD, &[0, 0, 0, 0], xmp].concat();
+            let at = chunks
+                .iter()
+                .position(|chunk| chunk.kind() == *b"IDAT")
+                .unwrap_or(chunks.len());
+            chunks.insert(at, PngChunk::new(*b"iTXt", Bytes::from(contents)));
+        }
+        Some(png.encoder().bytes().to_vec())
+    }
+
+    /// WebP keeps metadata in the extended format, where a VP8X chunk flags
+    /// each kind. ICC goes before the image data, EXIF and XMP after it.
+    fn write_webp(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let chunks = riff_chunks(data)?;
+        let image: Vec<_> = chunks
+            .iter()
+            .filter(|(id, _)| !matches!(id, b"VP8X" | b"ICCP" | b"EXIF" | b"XMP "))
+            .collect();
+        let vp8x = chunks.iter().find(|(id, _)| id == b"VP8X");
+        let has_metadata = self.exif.is_some() || self.icc.is_some() || self.xmp.is_some();
+        let mut header = match vp8x {
+            Some((_, body)) if body.len() >= 10 => body[..10].to_vec(),
+            Some(_) => return None,
+            // A simple file only needs upgrading when there's something to add
+            None if !has_metadata => return Some(data.to_vec()),
+            None => simple_webp_header(&chunks)?,
+        };
+        header[0] &= !(WEBP_ICC | WEBP_EXIF | WEBP_XMP);
+        for (flag, kind) in [
+            (WEBP_ICC, &self.icc),
+            (WEBP_EXIF, &self.exif),
+            (WEBP_XMP, &self.xmp),
+        ] {
+            if kind.is_some() {
+                header[0] |= flag;
+            }
+        }
+
+        let mut body = b"WEBP".to_vec();
+        push_riff_chunk(&mut body, b"VP8X", &header);
+        if let Some(icc) = &self.icc {
+            push_riff_chunk(&mut body, b"ICCP", icc);
+        }
+        for (id, chunk) in image {
+            push_riff_chunk(&mut body, id, chunk);
+        }
+        if let Some(exif) = &self.exif {
+            push_riff_chunk(&mut body, b"EXIF", exif);
+        }
+        if let Some(xmp) = &self.xmp {
+            push_riff_chunk(&mut body, b"XMP ", xmp);
+        }
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(&body);
+        Some(out)
+    }
+}
+
+fn read_jpeg(data: &[u8]) -> Metadata {
+    let Ok(jpeg) = Jpeg::from_bytes(Bytes::copy_from_slice(data)) else {
+        return Metadata::default();
+    };
+    Metadata {
+        exif: jpeg.exif().map(|exif| exif.to_vec()),
+        icc: jpeg.icc_profile().map(|icc| icc.to_vec()),
+        xmp: jpeg
+            .segments()
+            .iter()
+            .find_map(jpeg_xmp)
+            .map(<[u8]>::to_vec),
+        iptc: jpeg
+            .segments()
+            .iter()
+            .find_map(jpeg_iptc)
+            .map(<[u8]>::to_vec),
+    }
+}
+
+fn read_png(data: &[u8]) -> Metadata {
+    let Ok(png) = Png::from_bytes(Bytes::copy_from_slice(data)) else {
+        return Metadata::default();
+    };
+    Metadata {
+        exif: png.exif().map(|exif| exif.to_vec()),
+        icc: png.icc_profile().map(|icc| icc.to_vec()),
+        xmp: png.chunks().iter().find_map(png_xmp).map(<[u8]>::to_vec),
+        iptc: None,
+    }
+}
+
+fn read_webp(data: &[u8]) -> Metadata {
+    let chunks = riff_chunks(data).unwrap_or_default();
+    let chunk = |id: &[u8; 4]| {
+        chunks
+            .iter()
+            .find(|(chunk_id, _)| chunk_id == id)
+            .map(|(_, body)| body.to_vec())
+    };
+    Metadata {
+        // Some writers keep the JPEG APP1 prefix
+        exif: chunk(b"EXIF").map(|exif| match exif.strip_prefix(WEBP_EXIF_PREFIX) {
+            Some(tiff) => tiff.to_vec(),
+            None => exif,
+        }),
+        icc: chunk(b"ICCP"),
+        xmp: chunk(b"XMP "),
+        iptc: None,
+    }
+}
+
+fn jpeg_xmp(segment: &JpegSegment) -> Option<&[u8]> {
+    match segment.marker() {
+        0xE1 => segment.contents().strip_prefix(XMP_JPEG_PREFIX),
+        _ => None,
+    }
+}
+
+fn jpeg_iptc(segment: &JpegSegment) -> Option<&[u8]> {
+    match segment.marker() {
+        0xED => segment.contents().strip_prefix(IPTC_JPEG_PREFIX),
+        _ => None,
+    }
+}
+
+/// The packet of an uncompressed XMP text chunk.
+fn png_xmp(chunk: &PngChunk) -> Option<&[u8]> {
+    if chunk.kind() != *b"iTXt" {
+        return None;
+    }
+    let rest = chunk.contents().strip_prefix(XMP_PNG_KEYWORD)?;
+    let [0, _, rest @ ..] = rest else {
+        return None;
+    };
+    // Skip the language tag and translated keyword
+    rest.splitn(3, |byte| *byte == 0).nth(2)
+}
+
+/// Chunk IDs and bodies of a WebP file.
+fn riff_chunks(data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    if data.get(..4)? != b"RIFF" || data.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = data[offset..offset + 4].try_into().ok()?;
+        let size = u32::from_le_bytes(data[offset + 4..offset + 8].try_into().ok()?) as usize;
+        chunks.push((id, data.get(offset + 8..offset + 8 + size)?));
+        // Bodies are padded to an even length
+        offset += 8 + size + size % 2;
+    }
+    Some(chunks)
+}
+
+fn push_riff_chunk(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// A VP8X body for a simple WebP, with the canvas size from its bitstream.
+fn simple_webp_header(chunks: &[([u8; 4], &[u8])]) -> Option<Vec<u8>> {
+    const WEBP_ALPHA: u8 = 0x10;
+    let (id, body) = chunks.first()?;
+    let (width, height, flags) = match id {
+        b"VP8 " => {
+            let size = |at: usize| {
+                Some(u32::from(
+                    u16::from_le_bytes(body.get(at..at + 2)?.try_into().ok()?) & 0x3FFF,
+                ))
+            };
+            (size(6)?, size(8)?, 0)
+        }
+        b"VP8L" => {
+            let bits = u32::from_le_bytes(body.get(1..5)?.try_into().ok()?);
+            let alpha = match bits >> 28 & 1 {
+                1 => WEBP_ALPHA,
+                _ => 0,
+            };
+            ((bits & 0x3FFF) + 1, (bits >> 14 & 0x3FFF) + 1, alpha)
+        }
+        _ => return None,
+    };
+    let mut header = vec![flags, 0, 0, 0];
+    header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    Some(header)
+}
+
+/// Rewrite EXIF with only the fields `policy` keeps, or `None` when that
+/// leaves nothing.
+fn filter_exif(tiff: &[u8], policy: &MetadataPolicy, keep_orientation: bool) -> Option<Vec<u8>> {
+    let exif = exif::Reader::new().read_raw(tiff.to_vec()).ok()?;
+    let keeps = |field: &Field| {
+        if field.ifd_num == In::THUMBNAIL {
+            return policy.thumbnail;
+        }
+        match field.tag {
+            Tag(Context::Gps, _) => policy.gps,
+            Tag::Orientation => keep_orientation,
+            Tag::Artist | Tag::Copyright => policy.copyright,
+            // The source's size, which resizing would make wrong
+            Tag::PixelXDimension | Tag::PixelYDimension => false,
+            _ => policy.camera,
+        }
+    };
+    let fields: Vec<&Field> = exif
+        .fields()
+        .filter(|field| !matches!(field.value, Value::Unknown(..)) && keeps(field))
+        .collect();
+    if !fields.iter().any(|field| field.ifd_num == In::PRIMARY) {
+        return None;
+    }
+
+    let mut writer = Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let thumbnail = exif_thumbnail(&exif).filter(|_| policy.thumbnail);
+    if let Some(thumbnail) = thumbnail {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+    let mut out = Cursor::new(Vec::new());
+    writer.write(&mut out, exif.little_endian()).ok()?;
+    Some(out.into_inner())
+}
+
+fn exif_thumbnail(exif: &exif::Exif) -> Option<&[u8]> {
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let length = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    exif.buf().get(offset..offset + length)
+}
+
+/// Drop the XMP properties that repeat EXIF data `policy` strips. Only the
+/// namespace prefixes XMP writers use for them are recognized.
+fn filter_xmp(xmp: &[u8], policy: &MetadataPolicy, keep_orientation: bool) -> Vec<u8> {
+    let Ok(xmp) = std::str::from_utf8(xmp) else {
+        return xmp.to_vec();
+    };
+    let mut prefixes = Vec::new();
+    if !policy.gps {
+        prefixes.push("exif:GPS");
+    }
+    if !policy.camera {
+        prefixes.extend(["exif:", "exifEX:", "aux:", "tiff:"]);
+    }
+    if !policy.thumbnail {
+        prefixes.push("xmp:Thumbnails");
+    }
+    if !keep_orientation {
+        prefixes.push("tiff:Orientation");
+    }
+    strip_xmp_properties(xmp, &prefixes).into_bytes()
+}
+
+/// Remove elements and attributes whose names start with one of `prefixes`.
+fn strip_xmp_properties(xmp: &str, prefixes: &[&str]) -> String {
+    let named = |at: usize| prefixes.iter().any(|prefix| xmp[at..].starts_with(prefix));
+    let bytes = xmp.as_bytes();
+    let mut out = String::with_capacity(xmp.len());
+    let (mut copied, mut at) = (0, 0);
+    while at < bytes.len() {
+        let end = match bytes[at] {
+            b'<' if named(at + 1) => xmp_element_end(xmp, at),
+            byte if byte.is_ascii_whitespace() && named(at + 1) => xmp_attribute_end(xmp, at),
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                out.push_str(&xmp[copied..at]);
+                (copied, at) = (end, end);
+            }
+            None => at += 1,
+        }
+    }
+    out.push_str(&xmp[copied..]);
+    out
+}
+
+/// Where the element opened at `at` ends, after its closing tag.
+fn xmp_element_end(xmp: &str, at: usize) -> Option<usize> {
+    let name_len = xmp[at + 1..].find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+    let name = &xmp[at + 1..at + 1 + name_len];
+    let open_end = at + xmp[at..].find('>')? + 1;
+    if xmp[..open_end].ends_with("/>") {
+        return Some(open_end);
+    }
+    let close = format!("</{name}>");
+    Some(open_end + xmp[open_end..].find(&close)? + close.len())
+}
+
+/// Where the attribute after the whitespace at `at` ends, after its value.
+fn xmp_attribute_end(xmp: &str, at: usize) -> Option<usize> {
+    let equals = at + 1 + xmp[at + 1..].find('=')?;
+    if xmp[at + 1..equals].contains(|c: char| c.is_whitespace() || c == '<' || c == '>') {
+        return None;
+    }
+    let quote = *xmp.as_bytes().get(equals + 1)?;
+    if quote != b'"' && quote != b'\'' {
+        return None;
+    }
+    let close = xmp[equals + 2..].find(quote as char)?;
+    Some(equals + 2 + close + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        }
+    }
+
+    fn ascii(text: &str) -> Value {
+        Value::Ascii(vec![text.as_bytes().to_vec()])
+    }
+
+    fn exif() -> Vec<u8> {
+        let fields = [
+            field(Tag::Make, ascii("Camera")),
+            field(Tag::Artist, ascii("Staff")),
+            field(Tag::Copyright, ascii("Company")),
+            field(Tag::Orientation, Value::Short(vec![6])),
+            field(Tag::GPSLatitudeRef, ascii("N")),
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut out = Cursor::new(Vec::new());
+        writer.write(&mut out, false).unwrap();
+        out.into_inner()
+    }
+
+    fn tags(exif: &[u8]) -> Vec<Tag> {
+        let exif = exif::Reader::new().read_raw(exif.to_vec()).unwrap();
+        exif.fields().map(|field| field.tag).collect()
+    }
+
+    #[test]
+    fn filters_exif_by_kind() {
+        let policy = MetadataPolicy {
+            gps: false,
+            camera: false,
+            ..MetadataPolicy::default()
+        };
+        let filtered = filter_exif(&exif(), &policy, false).unwrap();
+        assert_eq!(tags(&filtered), [Tag::Artist, Tag::Copyright]);
+
+        let filtered = filter_exif(&exif(), &MetadataPolicy::default(), true).unwrap();
+        assert_eq!(tags(&filtered).len(), 5);
+
+        assert!(filter_exif(&exif(), &MetadataPolicy::none(), false).is_none());
+    }
+
+    #[test]
+    fn strips_xmp_properties() {
+        let xmp = r#"<rdf:Description exif:GPSLatitude="52,1N" dc:format="image/jpeg">
+<exif:GPSLongitude>4,5E</exif:GPSLongitude><exif:GPSAltitude/>
+<dc:rights><rdf:Alt><rdf:li xml:lang="x-default">Company</rdf:li></rdf:Alt></dc:rights>
+</rdf:Description>"#;
+        assert_eq!(
+            strip_xmp_properties(xmp, &["exif:GPS"]),
+            r#"<rdf:Description dc:format="image/jpeg">
+
+<dc:rights><rdf:Alt><rdf:li xml:lang="x-default">Company</rdf:li></rdf:Alt></dc:rights>
+</rdf:Description>"#
+        );
+    }
+
+    #[test]
+    fn writes_each_format() {
+        let metadata = Metadata {
+            exif: Some(exif()),
+            icc: Some(b"profile".to_vec()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+            iptc: None,
+        };
+        for (format, image_type) in [
+            (ImageFormat::Jpeg, ImageType::JPEG),
+            (ImageFormat::Png, ImageType::PNG),
+            (ImageFormat::WebP, ImageType::WEBP),
+        ] {
+            let mut data = Vec::new();
+            RgbImage::new(4, 4)
+                .write_to(&mut Cursor::new(&mut data), format)
+                .unwrap();
+            let written = metadata.write(data, &image_type);
+            assert!(image::load_from_memory(&written).is_ok(), "{format:?}");
+
+            let read = Metadata::read(&written, &MetadataPolicy::default(), true);
+            assert_eq!(read.exif.map(|exif| tags(&exif).len()), Some(5));
+            assert_eq!(read.icc, metadata.icc, "{format:?}");
+            assert_eq!(read.xmp, metadata.xmp, "{format:?}");
+
+            // Writing nothing strips it all again
+            let stripped = Metadata::default().write(written, &image_type);
+            let read = Metadata::read(&stripped, &MetadataPolicy::default(), true);
+            assert!(read.exif.is_none() && read.icc.is_none() && read.xmp.is_none());
+        }
+
+        // Left to their encoders, which `compress` tests
+        for image_type in [ImageType::TIFF, ImageType::GIF, ImageType::AVIF] {
+            assert!(!Metadata::writes(&image_type));
+            assert_eq!(metadata.write(b"data".to_vec(), &image_type), b"data");
+        }
+    }
+
+    #[test]
+    fn narrows_jpeg_repacked_into_jxl() {
+        let metadata = Metadata {
+            exif: Some(exif()),
+            icc: Some(b"profile".to_vec()),
+            xmp: Some(b"<x:xmpmeta/>".to_vec()),
+            iptc: None,
+        };
+        let mut data = Vec::new();
+        RgbImage::new(4, 4)
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = metadata.write(data, &ImageType::JPEG);
+
+        let kept = Metadata::narrow_jpeg(jpeg.clone(), &MetadataPolicy::all(), false);
+        assert!(kept == jpeg);
+
+        let policy = MetadataPolicy {
+            gps: false,
+            copyright: false,
+            ..MetadataPolicy::all()
+        };
+        let narrowed = Metadata::narrow_jpeg(jpeg.clone(), &policy, true);
+        let read = Metadata::read(&narrowed, &MetadataPolicy::all(), true);
+        let exif = read.exif.unwrap();
+        assert!(!tags(&exif).contains(&Tag::GPSLatitudeRef));
+        assert!(!tags(&exif).contains(&Tag::Artist));
+        // Kept even when stripped elsewhere, since repacking can't rotate
+        let policy = MetadataPolicy::none();
+        let narrowed = Metadata::narrow_jpeg(jpeg, &policy, false);
+        let read = Metadata::read(&narrowed, &MetadataPolicy::all(), true);
+        assert_eq!(
+            read.exif.map(|exif| tags(&exif)),
+            Some(vec![Tag::Orientation])
+        );
+        assert!(read.icc.is_some());
+        assert!(read.xmp.is_none() && read.iptc.is_none());
+        assert!(image::load_from_memory(&narrowed).is_ok());
+    }
+}
@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use specta::Type;
+use tauri_specta::Event;
+
+use crate::app_dirs;
+use crate::compress::{self, CompressResult};
+use crate::errors::{AlicError, AlicErrorType};
+use crate::events::JobResultEvent;
+use crate::jobs::{self, Job};
+use crate::report;
+use crate::settings::ProfileData;
+
+const QUEUE_FILE: &str = "queue.jsonl";
+
+/// The queue behind the app window, started once the app is set up.
+static APP_QUEUE: OnceLock<Arc<Queue>> = OnceLock::new();
+
+/// A file waiting in the queue, being processed, or failed and kept around
+/// so it can be retried.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedFile {
+    pub job_id: u32,
+    pub path: String,
+    pub root: Option<String>,
+    pub profile_id: u32,
+    // Higher goes first, then the order files were added in
+    pub priority: i32,
+    pub status: QueueStatus,
+    pub attempts: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+pub enum QueueStatus {
+    Queued,
+    Running,
+    Failed,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+pub struct QueueState {
+    pub paused: bool,
+    pub files: Vec<QueuedFile>,
+}
+
+/// One change to the queue, appended to the log as it happens. Replaying
+/// the log rebuilds the unfinished batch after a crash or restart.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+enum Change {
+    // The settings files added after it are processed with
+    Profile(Box<ProfileData>),
+    Added(QueuedFile),
+    Priority { job_id: u32, priority: i32 },
+    Removed { job_id: u32 },
+    Paused(bool),
+}
+
+/// A file done processing, as handed to the queue's owner.
+pub struct Finished<'a> {
+    pub file: &'a QueuedFile,
+    pub profile_name: &'a str,
+    pub result: &'a Result<CompressResult, AlicError>,
+    pub duration: Duration,
+}
+
+type OnFinish = Box<dyn Fn(Finished) + Send + Sync>;
+
+/// Files waiting to be processed by a pool of worker threads, as many as
+/// the `threads` setting allows.
+pub struct Queue {
+    state: Mutex<State>,
+    // Signalled when a worker may have something to do
+    wake: Condvar,
+    on_finish: OnFinish,
+    app: Option<tauri::AppHandle>,
+}
+
+struct State {
+    files: Vec<QueuedFile>,
+    profiles: HashMap<u32, ProfileData>,
+    paused: bool,
+    // Until the window is listening for results, which it says with
+    // `start_queue`. Not logged, unlike `paused`.
+    held: bool,
+    next_id: u32,
+    threads: usize,
+    workers: usize,
+    // Shared with the running jobs, which split the CPUs between them
+    running: Arc<AtomicUsize>,
+    log: Log,
+}
+
+impl Queue {
+    /// Resume the batch logged at `log_path`, if any, and start `threads`
+    /// workers on it. A queue reporting to the app holds its files until
+    /// `release` is called.
+    pub fn new(
+        log_path: Option<PathBuf>,
+        threads: usize,
+        app: Option<tauri::AppHandle>,
+        on_finish: OnFinish,
+    ) -> Arc<Self> {
+        let (log, changes) = Log::open(log_path);
+        let mut state = State {
+            files: Vec::new(),
+            profiles: HashMap::new(),
+            paused: false,
+            held: app.is_some(),
+            next_id: 0,
+            threads: 0,
+            workers: 0,
+            running: Arc::default(),
+            log,
+        };
+        for change in changes {
+            state.apply(change);
+        }
+        // Whatever was running when the app stopped starts over
+        for file in &mut state.files {
+            file.status = QueueStatus::Queued;
+        }
+        state.log.compact(&state.changes());
+        if !state.files.is_empty() {
+            debug!("Resuming {} queued files", state.files.len());
+        }
+
+        let queue = Arc::new(Queue {
+            state: Mutex::new(state),
+            wake: Condvar::new(),
+            on_finish,
+            app,
+        });
+        queue.set_threads(threads);
+        queue
+    }
+
+    pub fn add(&self, path: String, root: Option<String>, profile: ProfileData) -> QueuedFile {
+        let mut state = self.lock();
+        if state.profiles.get(&profile.id) != Some(&profile) {
+            state.record(Change::Profile(Box::new(profile.clone())));
+        }
+        let file = QueuedFile {
+            job_id: state.next_id,
+            path,
+            root,
+            profile_id: profile.id,
+            priority: 0,
+            status: QueueStatus::Queued,
+            attempts: 0,
+        };
+        state.record(Change::Added(file.clone()));
+        self.wake.notify_one();
+        file
+    }
+
+    pub fn snapshot(&self) -> QueueState {
+        let state = self.lock();
+        QueueState {
+            paused: state.paused,
+            files: state.files.clone(),
+        }
+    }
+
+    /// Grow or shrink the worker pool. Workers above the count stop once
+    /// they finish what they're on.
+    pub fn set_threads(self: &Arc<Self>, threads: usize) {
+        let mut state = self.lock();
+        state.threads = threads.max(1);
+        while state.workers < state.threads {
+            state.workers += 1;
+            let queue = Arc::clone(self);
+            thread::spawn(move || queue.work());
+        }
+        self.wake.notify_all();
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        let mut state = self.lock();
+        if state.paused != paused {
+            state.record(Change::Paused(paused));
+        }
+        self.wake.notify_all();
+    }
+
+    /// Let the workers take files, once their results have somewhere to go.
+    pub fn release(&self) {
+        self.lock().held = false;
+        self.wake.notify_all();
+    }
+
+    pub fn set_priority(&self, job_id: u32, priority: i32) -> Result<(), String> {
+        let mut state = self.lock();
+        state.find(job_id)?;
+        state.record(Change::Priority { job_id, priority });
+        Ok(())
+    }
+
+    /// Queue a failed file again.
+    pub fn retry(&self, job_id: u32) -> Result<(), String> {
+        let mut state = self.lock();
+        let file = &state.files[state.find(job_id)?];
+        if file.status != QueueStatus::Failed {
+            return Err("Only failed files can be retried".to_string());
+        }
+        let file = QueuedFile {
+            status: QueueStatus::Queued,
+            ..file.clone()
+        };
+        state.record(Change::Added(file));
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Take a file out of the queue, cancelling it if it's running. Returns
+    /// whether it was in the queue.
+    pub fn cancel(&self, job_id: u32) -> bool {
+        let mut state = self.lock();
+        let Ok(index) = state.find(job_id) else {
+            return false;
+        };
+        if state.files[index].status == QueueStatus::Running {
+            jobs::cancel(job_id);
+        }
+        state.record(Change::Removed { job_id });
+        state.compact_if_drained();
+        true
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        for file in &state.files {
+            if file.status == QueueStatus::Running {
+                jobs::cancel(file.job_id);
+            }
+        }
+        state.files.clear();
+        state.compact_if_drained();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn work(&self) {
+        loop {
+            let (file, profile, job) = {
+                let mut state = self.lock();
+                loop {
+                    if state.workers > state.threads {
+                        state.workers -= 1;
+                        return;
+                    }
+                    if let Some((file, profile, running)) = state.take_next() {
+                        // Registered before unlocking, so `cancel` can't
+                        // miss a running file
+                        let job = Job::start(file.job_id).in_pool(running);
+                        break (file, profile, job);
+                    }
+                    state = self.wake.wait(state).unwrap();
+                }
+            };
+
+            let started = Instant::now();
+            let profile_name = profile.name.clone();
+            let job = match &self.app {
+                Some(app) => job.reporting_to(app.clone()),
+                None => job,
+            };
+            // A panic in an encoder fails the file instead of the worker
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                compress::process_path(profile, file.path.clone(), file.root.clone(), &job)
+            }))
+            .unwrap_or_else(|_| {
+                Err(AlicError {
+                    error: "Processing panicked.".to_string(),
+                    error_type: AlicErrorType::Unknown,
+                })
+            });
+            drop(job);
+
+            let file = self.finish(file, &result);
+            (self.on_finish)(Finished {
+                file: &file,
+                profile_name: &profile_name,
+                result: &result,
+                duration: started.elapsed(),
+            });
+        }
+    }
+
+    /// Take `file` off the queue, keeping it as failed when it could be
+    /// retried.
+    fn finish(&self, file: QueuedFile, result: &Result<CompressResult, AlicError>) -> QueuedFile {
+        let mut state = self.lock();
+        state.running.fetch_sub(1, Ordering::Relaxed);
+        let failed = matches!(
+            result,
+            Err(AlicError {
+                error_type: AlicErrorType::Unknown
+                    | AlicErrorType::FileTooLarge
+                    | AlicErrorType::FileNotFound
+                    | AlicErrorType::UnsupportedFileType
+                    | AlicErrorType::ImageResizeError
+                    | AlicErrorType::TargetSizeNotMet,
+                ..
+            })
+        );
+        let mut file = QueuedFile {
+            attempts: file.attempts + 1,
+            ..file
+        };
+        // Cancelled and cleared files are already gone
+        if let Ok(index) = state.find(file.job_id) {
+            state.record(Change::Removed {
+                job_id: file.job_id,
+            });
+            if failed {
+                // Failures aren't logged, so they don't come back on restart
+                file.status = QueueStatus::Failed;
+                state.files.insert(index, file.clone());
+            }
+        }
+        state.compact_if_drained();
+        self.wake.notify_one();
+        file
+    }
+}
+
+impl State {
+    fn record(&mut self, change: Change) {
+        self.log.append(&change);
+        self.apply(change);
+    }
+
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Profile(profile) => {
+                self.profiles.insert(profile.id, *profile);
+            }
+            Change::Added(file) => {
+                self.next_id = self.next_id.max(file.job_id + 1);
+                match self.find(file.job_id) {
+                    Ok(index) => self.files[index] = file,
+                    Err(_) => self.files.push(file),
+                }
+            }
+            Change::Priority { job_id, priority } => {
+                if let Ok(index) = self.find(job_id) {
+                    self.files[index].priority = priority;
+                }
+            }
+            Change::Removed { job_id } => {
+                self.files.retain(|file| file.job_id != job_id);
+            }
+            Change::Paused(paused) => self.paused = paused,
+        }
+    }
+
+    fn find(&self, job_id: u32) -> Result<usize, String> {
+        self.files
+            .iter()
+            .position(|file| file.job_id == job_id)
+            .ok_or_else(|| format!("No queued file with job ID {job_id}"))
+    }
+
+    /// The highest priority queued file, marked as running, with its profile
+    /// and the count of running images its job shares CPUs with.
+    fn take_next(&mut self) -> Option<(QueuedFile, ProfileData, Arc<AtomicUsize>)> {
+        if self.paused || self.held {
+            return None;
+        }
+        let index = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| file.status == QueueStatus::Queued)
+            .max_by_key(|(_, file)| (file.priority, std::cmp::Reverse(file.job_id)))?
+            .0;
+        let file = &mut self.files[index];
+        file.status = QueueStatus::Running;
+        let file = file.clone();
+        let profile = self
+            .profiles
+            .get(&file.profile_id)
+            .cloned()
+            .unwrap_or_default();
+        self.running.fetch_add(1, Ordering::Relaxed);
+        Some((file, profile, self.running.clone()))
+    }
+
+    /// The fewest changes that rebuild the unfinished batch.
+    fn changes(&self) -> Vec<Change> {
+        let unfinished: Vec<&QueuedFile> = self
+            .files
+            .iter()
+            .filter(|file| file.status != QueueStatus::Failed)
+            .collect();
+        let mut changes = vec![Change::Paused(self.paused)];
+        changes.extend(
+            self.profiles
+                .values()
+                .filter(|profile| unfinished.iter().any(|file| file.profile_id == profile.id))
+                .map(|profile| Change::Profile(Box::new(profile.clone()))),
+        );
+        changes.extend(
+            unfinished
+                .into_iter()
+                .map(|file| Change::Added(file.clone())),
+        );
+        changes
+    }
+
+    /// Start the log over once the batch is done, so it doesn't keep growing
+    /// across batches.
+    fn compact_if_drained(&mut self) {
+        if self
+            .files
+            .iter()
+            .all(|file| file.status == QueueStatus::Failed)
+        {
+            self.log.compact(&self.changes());
+        }
+    }
+}
+
+/// The queue's changes, kept in an append-only JSON Lines file like the
+/// cache's, so a crash loses at most the change being written.
+struct Log {
+    path: Option<PathBuf>,
+    // The file ends in a line cut short by a crash
+    torn: bool,
+}
+
+impl Log {
+    fn open(path: Option<PathBuf>) -> (Self, Vec<Change>) {
+        let contents = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        // A line cut short by a crash is skipped, not fatal
+        let changes = contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let torn = !contents.is_empty() && !contents.ends_with('\n');
+        (Self { path, torn }, changes)
+    }
+
+    fn append(&mut self, change: &Change) {
+        if let Some(path) = &self.path {
+            match write_lines(path, std::slice::from_ref(change), true, self.torn) {
+                Ok(()) => self.torn = false,
+                Err(e) => debug!("Could not write queue change: {e}"),
+            }
+        }
+    }
+
+    /// Replace the log with `changes`. The new file is written next to it
+    /// and renamed over it, so the old one stays whole until then.
+    fn compact(&mut self, changes: &[Change]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let temp_path = path.with_extension("jsonl.tmp");
+        let result = write_lines(&temp_path, changes, false, false)
+            .and_then(|()| fs::rename(&temp_path, path).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.torn = false,
+            Err(e) => debug!("Could not compact the queue: {e}"),
+        }
+    }
+}
+
+/// Write `changes` to `path`, after ending the torn line it has, if any, so
+/// the first of them stays readable.
+fn write_lines(path: &Path, changes: &[Change], append: bool, torn: bool) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut lines = match torn {
+        true => "\n".to_string(),
+        false => String::new(),
+    };
+    for change in changes {
+        lines.push_str(&serde_json::to_string(change).map_err(|e| e.to_string())?);
+        lines.push('\n');
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .map_err(|e| e.to_string())
+}
+
+/// Start the app's queue on the batch left from last time, if any.
+pub fn start(app: &tauri::AppHandle, threads: i32) {
+    let handle = app.clone();
+    let queue = Queue::new(
+        app_dirs::data_file(QUEUE_FILE),
+        thread_count(threads),
+        Some(app.clone()),
+        Box::new(move |finished| {
+            report::record(&finished);
+            let file = finished.file;
+            let (result, error) = match finished.result {
+                Ok(result) => (Some(result.clone()), None),
+                Err(error) => (None, Some(error.clone())),
+            };
+            let _ = JobResultEvent {
+                job_id: file.job_id,
+                path: file.path.clone(),
+                result,
+                error,
+            }
+            .emit(&handle);
+        }),
+    );
+    let _ = APP_QUEUE.set(queue);
+}
+
+/// Follow a change to the `threads` setting, where 0 means one per CPU.
+pub fn set_threads(threads: i32) {
+    if let Some(queue) = APP_QUEUE.get() {
+        queue.set_threads(thread_count(threads));
+    }
+}
+
+fn thread_count(threads: i32) -> usize {
+    match threads {
+        1.. => threads as usize,
+        _ => compress::num_cpus(),
+    }
+}
+
+fn app_queue() -> Result<&'static Arc<Queue>, String> {
+    APP_QUEUE
+        .get()
+        .ok_or_else(|| "The queue hasn't started yet".to_string())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn enqueue_file(
+    path: String,
+    root: Option<String>,
+    profile: ProfileData,
+) -> Result<QueuedFile, String> {
+    Ok(app_queue()?.add(path, root, profile))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn get_queue() -> Result<QueueState, String> {
+    Ok(app_queue()?.snapshot())
+}
+
+/// Start processing, called by the window once it listens for results.
+#[tauri::command]
+#[specta::specta]
+pub fn start_queue() -> Result<(), String> {
+    app_queue()?.release();
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_queue_paused(paused: bool) -> Result<(), String> {
+    app_queue()?.set_paused(paused);
+    Ok(())
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn set_job_priority(job_id: u32, priority: i32) -> Result<(), String> {
+    app_queue()?.set_priority(job_id, priority)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn retry_job(job_id: u32) -> Result<(), String> {
+    app_queue()?.retry(job_id)
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn cancel_job(job_id: u32) -> bool {
+    APP_QUEUE.get().is_some_and(|queue| queue.cancel(job_id))
+}
+
+#[tauri::command]
+#[specta::specta]
+pub fn clear_queue() {
+    if let Some(queue) = APP_QUEUE.get() {
+        queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn log_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("alic-queue-{name}-{}.jsonl", std::process::id()))
+    }
+
+    #[test]
+    fn resumes_unfinished_batch() {
+        let path = log_path("resume");
+        let _ = fs::remove_file(&path);
+        let queue = Queue::new(Some(path.clone()), 1, None, Box::new(|_| {}));
+        queue.set_paused(true);
+        let profile = ProfileData::new();
+        let first = queue.add("a.png".to_string(), None, profile.clone());
+        let second = queue.add("b.png".to_string(), Some("dir".to_string()), profile);
+        queue.set_priority(second.job_id, 5).unwrap();
+        assert!(queue.cancel(first.job_id));
+        assert!(!queue.cancel(first.job_id));
+        // A change torn by a crash
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"Removed\":{\"job")
+            .unwrap();
+
+        let resumed = Queue::new(Some(path.clone()), 1, None, Box::new(|_| {}));
+        let state = resumed.snapshot();
+        assert!(state.paused);
+        assert_eq!(
+            state.files,
+            [QueuedFile {
+                priority: 5,
+                ..second
+            }]
+        );
+        // IDs keep counting from the resumed batch
+        let third = resumed.add("c.png".to_string(), None, ProfileData::new());
+        assert!(third.job_id > second.job_id);
+
+        // Draining the queue starts the log over
+        assert!(resumed.cancel(second.job_id));
+        assert!(resumed.cancel(third.job_id));
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn runs_by_priority_and_keeps_failures() {
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let queue = Queue::new(
+            None,
+            1,
+            None,
+            Box::new(move |finished| {
+                let error_type = finished
+                    .result
+                    .as_ref()
+                    .err()
+                    .map(|e| format!("{:?}", e.error_type));
+                tx.lock()
+                    .unwrap()
+                    .send((finished.file.clone(), error_type))
+                    .unwrap();
+            }),
+        );
+        queue.set_paused(true);
+        let profile = ProfileData::new();
+        let low = queue.add("/nonexistent/low.png".to_string(), None, profile.clone());
+        let high = queue.add("/nonexistent/high.png".to_string(), None, profile);
+        queue.set_priority(high.job_id, 1).unwrap();
+        queue.set_paused(false);
+
+        let receive = || rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        let (first, error_type) = receive();
+        assert_eq!(first.job_id, high.job_id);
+        assert_eq!(first.status, QueueStatus::Failed);
+        assert_eq!(error_type.as_deref(), Some("UnsupportedFileType"));
+        assert_eq!(receive().0.job_id, low.job_id);
+
+        assert!(queue.retry(low.job_id).is_ok());
+        assert_eq!(receive().0.attempts, 2);
+        assert!(queue.cancel(high.job_id));
+        assert_eq!(queue.snapshot().files.len(), 1);
+        assert!(queue.retry(high.job_id).is_err());
+    }
+}
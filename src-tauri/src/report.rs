@@ -0,0 +1,268 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+use crate::compress::ImageType;
+use crate::queue::Finished;
+
+/// The rows of every image processed by the app since the files were last
+/// cleared.
+static SESSION: LazyLock<Mutex<Vec<ReportRow>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+const HEADER: [&str; 15] = [
+    "input_path",
+    "output_path",
+    "original_size",
+    "final_size",
+    "savings_percent",
+    "format_before",
+    "format_after",
+    "width_before",
+    "height_before",
+    "width_after",
+    "height_after",
+    "profile",
+    "quality",
+    "duration_ms",
+    "error_type",
+];
+
+/// One output of an image, or the image itself when it failed or was
+/// skipped.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+pub struct ReportRow {
+    pub input_path: String,
+    pub output_path: Option<String>,
+    pub original_size: Option<u32>,
+    pub final_size: Option<u32>,
+    pub savings_percent: Option<f32>,
+    pub format_before: Option<ImageType>,
+    pub format_after: Option<ImageType>,
+    pub width_before: Option<u32>,
+    pub height_before: Option<u32>,
+    pub width_after: Option<u32>,
+    pub height_after: Option<u32>,
+    pub profile: String,
+    pub quality: Option<u32>,
+    pub duration_ms: u32,
+    pub error_type: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl ReportFormat {
+    /// Pick the format from the report's file extension.
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("csv") => Ok(ReportFormat::Csv),
+            Some("json") => Ok(ReportFormat::Json),
+            _ => Err(format!("Report {path} must end in .csv or .json")),
+        }
+    }
+}
+
+/// The report rows for a file the queue finished.
+pub fn rows(finished: &Finished) -> Vec<ReportRow> {
+    let row = ReportRow {
+        input_path: finished.file.path.clone(),
+        output_path: None,
+        original_size: None,
+        final_size: None,
+        savings_percent: None,
+        format_before: None,
+        format_after: None,
+        width_before: None,
+        height_before: None,
+        width_after: None,
+        height_after: None,
+        profile: finished.profile_name.to_string(),
+        quality: None,
+        duration_ms: finished.duration.as_millis() as u32,
+        error_type: None,
+    };
+    let result = match finished.result {
+        Ok(result) => result,
+        Err(error) => {
+            return vec![ReportRow {
+                original_size: fs::metadata(&row.input_path)
+                    .ok()
+                    .map(|metadata| metadata.len() as u32),
+                error_type: Some(format!("{:?}", error.error_type)),
+                ..row
+            }];
+        }
+    };
+    result
+        .outputs
+        .iter()
+        .map(|output| ReportRow {
+            output_path: Some(output.path.clone()),
+            original_size: Some(result.original_size),
+            final_size: Some(output.size),
+            savings_percent: savings_percent(result.original_size, output.size),
+            format_before: Some(result.original_format.clone()),
+            format_after: Some(output.format.clone()),
+            width_before: result.original_width,
+            height_before: result.original_height,
+            width_after: output.width,
+            height_after: output.height,
+            quality: output.quality,
+            ..row.clone()
+        })
+        .collect()
+}
+
+/// Saved space as a percent of the original, to one decimal.
+fn savings_percent(original_size: u32, final_size: u32) -> Option<f32> {
+    if original_size == 0 {
+        return None;
+    }
+    let percent = (1.0 - final_size as f64 / original_size as f64) * 100.0;
+    Some(((percent * 10.0).round() / 10.0) as f32)
+}
+
+pub fn write(path: &str, rows: &[ReportRow]) -> Result<(), String> {
+    let contents = match ReportFormat::from_path(path)? {
+        ReportFormat::Csv => to_csv(rows),
+        ReportFormat::Json => serde_json::to_string_pretty(rows).map_err(|e| e.to_string())?,
+    };
+    fs::write(path, contents).map_err(|e| format!("Could not write report {path}: {e}"))
+}
+
+fn to_csv(rows: &[ReportRow]) -> String {
+    let mut csv = HEADER.join(",");
+    csv.push('\n');
+    for row in rows {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let number = |value: Option<u32>| optional(value.map(|value| value.to_string()));
+        let format = |value: &Option<ImageType>| optional(value.as_ref().map(|f| format!("{f:?}")));
+        let fields = [
+            csv_field(&row.input_path),
+            csv_field(row.output_path.as_deref().unwrap_or_default()),
+            number(row.original_size),
+            number(row.final_size),
+            optional(row.savings_percent.map(|percent| format!("{percent:.1}"))),
+            format(&row.format_before),
+            format(&row.format_after),
+            number(row.width_before),
+            number(row.height_before),
+            number(row.width_after),
+            number(row.height_after),
+            csv_field(&row.profile),
+            number(row.quality),
+            row.duration_ms.to_string(),
+            optional(row.error_type.clone()),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quote a field when it holds a comma, quote or line break.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// Add the rows of a file the app finished, replacing those of an earlier
+/// attempt at it.
+pub fn record(finished: &Finished) {
+    let mut session = SESSION.lock().unwrap();
+    session.retain(|row| row.input_path != finished.file.path);
+    session.extend(rows(finished));
+}
+
+pub fn clear_session() {
+    SESSION.lock().unwrap().clear();
+}
+
+/// Write a report of the files processed since they were last cleared, as
+/// CSV or JSON depending on the extension of `path`.
+#[tauri::command]
+#[specta::specta]
+pub fn export_report(path: String) -> Result<(), String> {
+    let rows = SESSION.lock().unwrap().clone();
+    write(&path, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::{CompressResult, OutputFile};
+    use crate::errors::{AlicError, AlicErrorType};
+    use crate::queue::{QueueStatus, QueuedFile};
+    use std::time::Duration;
+
+    #[test]
+    fn reports_outputs_and_failures() {
+        let file = QueuedFile {
+            job_id: 0,
+            path: "/photos/a, b.png".to_string(),
+            root: None,
+            profile_id: 0,
+            priority: 0,
+            status: QueueStatus::Running,
+            attempts: 0,
+        };
+        let result = Ok(CompressResult {
+            path: file.path.clone(),
+            out_size: 250,
+            out_path: "/photos/a, b.min.webp".to_string(),
+            result: "Success".to_string(),
+            quality: Some(80),
+            outputs: vec![OutputFile {
+                path: "/photos/a, b.min.webp".to_string(),
+                size: 250,
+                format: ImageType::WEBP,
+                quality: Some(80),
+                width: Some(50),
+                height: Some(25),
+            }],
+            snippet: None,
+            original_size: 1000,
+            original_format: ImageType::PNG,
+            original_width: Some(100),
+            original_height: Some(50),
+        });
+        let finished = |result| Finished {
+            file: &file,
+            profile_name: "Web \"small\"",
+            result,
+            duration: Duration::from_millis(1200),
+        };
+
+        let compressed = rows(&finished(&result));
+        assert_eq!(compressed.len(), 1);
+        assert_eq!(compressed[0].savings_percent, Some(75.0));
+        assert_eq!(
+            to_csv(&compressed).lines().nth(1),
+            Some(
+                "\"/photos/a, b.png\",\"/photos/a, b.min.webp\",1000,250,75.0,PNG,WEBP,\
+                 100,50,50,25,\"Web \"\"small\"\"\",80,1200,"
+            )
+        );
+
+        let failed = rows(&finished(&Err(AlicError {
+            error: "Not found.".to_string(),
+            error_type: AlicErrorType::FileNotFound,
+        })));
+        assert_eq!(failed[0].output_path, None);
+        assert_eq!(failed[0].error_type.as_deref(), Some("FileNotFound"));
+
+        assert_eq!(
+            ReportFormat::from_path("a/report.JSON"),
+            Ok(ReportFormat::Json)
+        );
+        assert!(ReportFormat::from_path("report.txt").is_err());
+    }
+}
@@ -1,13 +1,12 @@
 use std::io::Cursor;
 
-use image::codecs::gif::{GifDecoder, GifEncoder};
+use chrono::{NaiveDate, NaiveDateTime};
 use image::imageops::FilterType;
 use image::metadata::Orientation;
-use image::{
-    AnimationDecoder, DynamicImage, Frame, ImageDecoder, ImageFormat, ImageReader, Limits,
-};
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
 use log::debug;
 
+use crate::animation;
 use crate::errors::AlicError;
 use crate::errors::AlicErrorType;
 
@@ -62,10 +61,11 @@ pub fn resize(
     height: u32,
     should_background_fill: bool,
     background_fill: &str,
-    is_gif: bool,
+    is_animated: bool,
+    gif_quality: u32,
 ) -> Result<Vec<u8>, AlicError> {
-    if is_gif {
-        return resize_gif(&image_buffer, width, height);
+    if is_animated {
+        return resize_animation(&image_buffer, width, height, gif_quality);
     }
 
     let (mut image, format) = read_image(&image_buffer)?;
@@ -96,75 +96,29 @@ pub fn resize(
     Ok(resized_file_buffer)
 }
 
-fn resize_gif(image_buffer: &[u8], width: u32, height: u32) -> Result<Vec<u8>, AlicError> {
-    let decoder = GifDecoder::new(Cursor::new(image_buffer)).map_err(|e| AlicError {
-        error: e.to_string(),
-        error_type: AlicErrorType::ImageResizeError,
-    })?;
-
-    let (src_width, src_height) = decoder.dimensions();
-    let frames = decoder
-        .into_frames()
-        .collect_frames()
-        .map_err(|e| AlicError {
-            error: e.to_string(),
-            error_type: AlicErrorType::ImageResizeError,
-        })?;
+/// Resize every frame of an animated GIF, PNG or WebP and encode it back
+/// to the same format.
+fn resize_animation(
+    image_buffer: &[u8],
+    width: u32,
+    height: u32,
+    gif_quality: u32,
+) -> Result<Vec<u8>, AlicError> {
+    let mut animation = animation::decode(image_buffer)?;
+    let (src_width, src_height) = animation.dimensions();
 
     // Check if resize is needed
     if src_width <= width && src_height <= height {
         return Ok(image_buffer.to_vec());
     }
 
-    // Calculate new dimensions preserving aspect ratio
-    let ratio_w = width as f64 / src_width as f64;
-    let ratio_h = height as f64 / src_height as f64;
-    let ratio = ratio_w.min(ratio_h);
-
-    let mut new_frames = Vec::new();
-
-    for frame in frames {
-        let frame_buffer = frame.buffer();
-        let frame_width = frame_buffer.width();
-        let frame_height = frame_buffer.height();
-
-        // Calculate new frame dimensions
-        let new_frame_width = (frame_width as f64 * ratio).round() as u32;
-        let new_frame_height = (frame_height as f64 * ratio).round() as u32;
-
-        // Skip empty frames if any (though unlikely to have 0 dims)
-        if new_frame_width == 0 || new_frame_height == 0 {
-            continue;
-        }
-
-        let dynamic_image = DynamicImage::ImageRgba8(frame_buffer.clone());
-        let resized_dynamic =
-            dynamic_image.resize(new_frame_width, new_frame_height, FilterType::Lanczos3);
-        let resized_buffer = resized_dynamic.into_rgba8();
-
-        let left = (frame.left() as f64 * ratio).round() as u32;
-        let top = (frame.top() as f64 * ratio).round() as u32;
-
-        let new_frame = Frame::from_parts(resized_buffer, left, top, frame.delay());
-        new_frames.push(new_frame);
-    }
-
-    let mut out_buffer = Vec::new();
-    {
-        let mut encoder = GifEncoder::new(&mut out_buffer);
-        encoder
-            .set_repeat(image::codecs::gif::Repeat::Infinite)
-            .map_err(|e| AlicError {
-                error: e.to_string(),
-                error_type: AlicErrorType::ImageResizeError,
-            })?;
-        encoder.encode_frames(new_frames).map_err(|e| AlicError {
-            error: e.to_string(),
-            error_type: AlicErrorType::ImageResizeError,
-        })?;
-    }
-
-    Ok(out_buffer)
+    // Frames are composited onto the full canvas, so they all share the
+    // canvas's new size
+    let ratio = (width as f64 / src_width as f64).min(height as f64 / src_height as f64);
+    let new_width = ((src_width as f64 * ratio).round() as u32).max(1);
+    let new_height = ((src_height as f64 * ratio).round() as u32).max(1);
+    animation.resize(new_width, new_height);
+    animation::encode(&animation, animation.format, gif_quality)
 }
 
 fn add_background(
@@ -186,8 +140,8 @@ fn add_background(
     Ok(bg_image)
 }
 
-fn read_image(image_buffer: &Vec<u8>) -> Result<(DynamicImage, ImageFormat), AlicError> {
-    let mut reader = ImageReader::new(Cursor::new(&image_buffer));
+pub(crate) fn read_image(image_buffer: &[u8]) -> Result<(DynamicImage, ImageFormat), AlicError> {
+    let mut reader = ImageReader::new(Cursor::new(image_buffer));
     let mut limits = Limits::default();
     // 2gb limit
     limits.max_alloc = Some(2 * 1024 * 1024 * 1024);
@@ -212,16 +166,28 @@ fn read_image(image_buffer: &Vec<u8>) -> Result<(DynamicImage, ImageFormat), Ali
     Ok((image, format))
 }
 
+/// Width and height from the image header, without decoding the pixels.
+pub fn image_dimensions(image_buffer: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(image_buffer))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
 /// Bake any EXIF orientation into the pixels and return re-encoded bytes in the
 /// same format, so downstream compressors produce an upright image even when
 /// metadata is stripped or the target format can't carry orientation.
 ///
 /// This is a cheap no-op for images with no orientation (or identity
 /// orientation): they are returned untouched without a re-encode. Only images
-/// that actually need rotating pay the decode/encode cost. GIFs are returned
-/// as-is to avoid collapsing animation into a single frame.
-pub fn normalize_orientation(image_buffer: Vec<u8>, is_gif: bool) -> Result<Vec<u8>, AlicError> {
-    if is_gif || get_exif_orientation(&image_buffer) == 1 {
+/// that actually need rotating pay the decode/encode cost. Animations are
+/// returned as-is to avoid collapsing them into a single frame.
+pub fn normalize_orientation(
+    image_buffer: Vec<u8>,
+    is_animated: bool,
+) -> Result<Vec<u8>, AlicError> {
+    if is_animated || get_exif_orientation(&image_buffer) == 1 {
         return Ok(image_buffer);
     }
 
@@ -240,6 +206,23 @@ pub fn normalize_orientation(image_buffer: Vec<u8>, is_gif: bool) -> Result<Vec<
     Ok(out)
 }
 
+/// When the photo was taken, from the EXIF `DateTimeOriginal` tag.
+pub fn capture_date(data: &[u8]) -> Option<NaiveDateTime> {
+    let exif_data = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()?;
+    let field = exif_data.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(ref values) = field.value else {
+        return None;
+    };
+    let date = exif::DateTime::from_ascii(values.first()?).ok()?;
+    NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?.and_hms_opt(
+        date.hour.into(),
+        date.minute.into(),
+        date.second.into(),
+    )
+}
+
 fn get_exif_orientation(data: &[u8]) -> u32 {
     let reader = exif::Reader::new();
     let mut cursor = Cursor::new(data);
@@ -8,6 +8,7 @@ use tauri_plugin_store::{Store, StoreExt};
 use tauri_specta::Event;
 
 use crate::compress::ImageType;
+use crate::template::Template;
 
 const SETTINGS_KEY: &str = "settings";
 
@@ -52,7 +53,7 @@ impl SettingsData {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
 #[serde(default)]
 pub struct ProfileData {
     pub name: String,
@@ -63,7 +64,8 @@ pub struct ProfileData {
     pub should_overwrite: bool,
     pub enable_lossy: bool,
     pub keep_timestamps: bool,
-    pub keep_metadata: bool,
+    pub metadata: MetadataPolicy,
+    pub color_profile: ColorProfilePolicy,
     #[serde(alias = "add_posfix")]
     pub add_postfix: bool,
     pub should_background_fill: bool,
@@ -77,6 +79,154 @@ pub struct ProfileData {
     pub webp_quality: u32,
     pub gif_quality: u32,
     pub avif_quality: u32,
+    pub jxl_quality: u32,
+    pub should_target_size: bool,
+    pub target_size_kb: u32,
+    pub should_target_similarity: bool,
+    pub target_similarity: f64,
+    pub convert_mode: ConvertMode,
+    pub convert_formats: Vec<ImageType>,
+    pub should_responsive: bool,
+    pub responsive_widths: Vec<u32>,
+    pub should_output_dir: bool,
+    pub output_dir: String,
+    pub flatten_output: bool,
+    pub should_template: bool,
+    pub filename_template: String,
+    pub on_collision: CollisionPolicy,
+    pub should_cache: bool,
+    pub min_savings: u32,
+    pub min_savings_unit: SavingsUnit,
+    pub not_smaller: NotSmallerPolicy,
+    pub should_mark_outputs: bool,
+    pub jpeg_progressive: bool,
+    pub jpeg_chroma_subsampling: ChromaSubsampling,
+    pub png_optimization_level: u8,
+    pub png_colors: u32,
+    pub png_dithering: f64,
+    pub webp_method: u32,
+    pub webp_near_lossless: u32,
+    pub avif_speed: u8,
+    pub should_avif_alpha_quality: bool,
+    pub avif_alpha_quality: u32,
+    pub jxl_lossless_jpeg: bool,
+}
+
+/// Which kinds of metadata outputs keep. Anything not kept is stripped.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MetadataPolicy {
+    // GPS coordinates
+    pub gps: bool,
+    // Camera make and model, exposure, capture date and other EXIF data
+    pub camera: bool,
+    // EXIF artist and copyright, IPTC and XMP
+    pub copyright: bool,
+    // The small preview EXIF can embed
+    pub thumbnail: bool,
+    // The EXIF orientation flag. Without it the pixels are rotated instead
+    pub orientation: bool,
+}
+
+impl Default for MetadataPolicy {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl MetadataPolicy {
+    pub fn all() -> Self {
+        Self {
+            gps: true,
+            camera: true,
+            copyright: true,
+            thumbnail: true,
+            orientation: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            gps: false,
+            camera: false,
+            copyright: false,
+            thumbnail: false,
+            orientation: false,
+        }
+    }
+}
+
+/// What happens to the source's ICC color profile.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq, Default)]
+pub enum ColorProfilePolicy {
+    // Embed the profile, converting to sRGB instead when an output format
+    // can't carry one
+    #[default]
+    Keep,
+    // Convert the pixels to sRGB and leave the profile out
+    ConvertToSrgb,
+    // Leave the profile out without converting, so wide-gamut colors dull
+    Strip,
+}
+
+/// JPEG chroma subsampling.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq, Default)]
+pub enum ChromaSubsampling {
+    // Let the encoder pick based on quality
+    #[default]
+    Auto,
+    // Full color resolution
+    CS444,
+    // Half horizontal color resolution
+    CS422,
+    // Half horizontal and vertical color resolution
+    CS420,
+}
+
+/// How `should_convert` picks the output format.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq, Default)]
+pub enum ConvertMode {
+    // Always convert to `convert_extension`
+    #[default]
+    Single,
+    // Encode every format in `convert_formats` and keep the smallest
+    Smallest,
+    // Write every format in `convert_formats`, e.g. for <picture> fallbacks
+    All,
+}
+
+/// What to do when something already exists at an output path.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq, Default)]
+pub enum CollisionPolicy {
+    // Move the existing file to the trash
+    #[default]
+    Replace,
+    // Keep the existing file and don't write the output
+    Skip,
+    // Write `name.min (2).webp` instead, counting up until a name is free
+    Rename,
+    // Fail the image
+    Error,
+}
+
+/// How `min_savings` is measured against the original's size.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq, Default)]
+pub enum SavingsUnit {
+    #[default]
+    Percent,
+    Bytes,
+}
+
+/// What to do with an output that doesn't save `min_savings`.
+#[derive(serde::Serialize, serde::Deserialize, Type, Debug, Clone, PartialEq, Default)]
+pub enum NotSmallerPolicy {
+    // Don't write the output
+    #[default]
+    Skip,
+    // Write the original instead, so an output folder has every image
+    CopyOriginal,
+    // Write the output anyway
+    Write,
 }
 
 fn default_color() -> String {
@@ -96,7 +246,8 @@ impl Default for ProfileData {
             should_overwrite: false,
             enable_lossy: true,
             keep_timestamps: false,
-            keep_metadata: true,
+            metadata: MetadataPolicy::default(),
+            color_profile: ColorProfilePolicy::Keep,
             add_postfix: true,
             convert_extension: ImageType::WEBP,
             postfix: ".min".to_string(),
@@ -107,6 +258,37 @@ impl Default for ProfileData {
             webp_quality: 80,
             gif_quality: 80,
             avif_quality: 80,
+            jxl_quality: 80,
+            should_target_size: false,
+            target_size_kb: 500,
+            should_target_similarity: false,
+            target_similarity: 0.98,
+            convert_mode: ConvertMode::Single,
+            convert_formats: vec![ImageType::WEBP, ImageType::AVIF, ImageType::JPEG],
+            should_responsive: false,
+            responsive_widths: vec![480, 960, 1920],
+            should_output_dir: false,
+            output_dir: String::new(),
+            flatten_output: false,
+            should_template: false,
+            filename_template: "{name}.min.{ext}".to_string(),
+            on_collision: CollisionPolicy::Replace,
+            should_cache: true,
+            min_savings: 5,
+            min_savings_unit: SavingsUnit::Percent,
+            not_smaller: NotSmallerPolicy::Skip,
+            should_mark_outputs: false,
+            jpeg_progressive: true,
+            jpeg_chroma_subsampling: ChromaSubsampling::Auto,
+            png_optimization_level: 3,
+            png_colors: 256,
+            png_dithering: 1.0,
+            webp_method: 4,
+            webp_near_lossless: 100,
+            avif_speed: 4,
+            should_avif_alpha_quality: false,
+            avif_alpha_quality: 80,
+            jxl_lossless_jpeg: true,
         }
     }
 }
@@ -124,6 +306,29 @@ impl ProfileData {
             ..Self::default()
         }
     }
+
+    /// The formats images are converted to, or none when they keep their
+    /// own.
+    pub fn convert_targets(&self) -> Vec<ImageType> {
+        if !self.should_convert {
+            return Vec::new();
+        }
+        match self.convert_mode {
+            ConvertMode::Single => vec![self.convert_extension.clone()],
+            ConvertMode::Smallest | ConvertMode::All => {
+                let mut formats: Vec<ImageType> = Vec::new();
+                for format in &self.convert_formats {
+                    if !formats.contains(format) {
+                        formats.push(format.clone());
+                    }
+                }
+                if formats.is_empty() {
+                    formats.push(self.convert_extension.clone());
+                }
+                formats
+            }
+        }
+    }
 }
 
 #[derive(serde::Serialize, Type, Debug, Clone)]
@@ -155,10 +360,80 @@ pub async fn get_settings(app: tauri::AppHandle) -> Result<SettingsResult, Strin
 #[tauri::command]
 #[specta::specta]
 pub async fn save_settings(app: tauri::AppHandle, settings: SettingsData) -> Result<(), String> {
+    validate_settings(&settings)?;
+    crate::queue::set_threads(settings.threads);
     set_settings_data(&app, settings);
     Ok(())
 }
 
+fn validate_settings(settings: &SettingsData) -> Result<(), String> {
+    for profile in &settings.profiles {
+        validate_profile(profile).map_err(|e| format!("Profile \"{}\": {e}", profile.name))?;
+    }
+    Ok(())
+}
+
+/// Check a profile for settings that can't work, alone or together. The
+/// CLI checks its profile again once flags have overridden it.
+pub fn validate_profile(profile: &ProfileData) -> Result<(), String> {
+    if profile.should_template {
+        Template::parse(&profile.filename_template)?;
+    }
+    validate_encoder_settings(profile)?;
+    // HEIC is read through ImageIO but there's no encoder for it
+    if profile.convert_extension == ImageType::HEIC
+        || profile.convert_formats.contains(&ImageType::HEIC)
+    {
+        return Err("HEIC can't be an output format.".to_string());
+    }
+    // Each target picks the quality on its own
+    if profile.should_target_size && profile.should_target_similarity {
+        return Err("Target file size and target similarity can't be used together.".to_string());
+    }
+    // Similarity is scored by decoding the output, and AVIF can't be decoded
+    if profile.should_target_similarity && profile.convert_targets().contains(&ImageType::AVIF) {
+        return Err("Target similarity doesn't work with AVIF output.".to_string());
+    }
+    // The lossy WebP encoder ignores near-lossless
+    if profile.enable_lossy && profile.webp_near_lossless < 100 {
+        return Err("WebP near-lossless needs lossy compression off.".to_string());
+    }
+    Ok(())
+}
+
+/// Check the per-format encoder settings against what the encoders accept.
+fn validate_encoder_settings(profile: &ProfileData) -> Result<(), String> {
+    let in_range = |name: &str, value: f64, min: f64, max: f64| {
+        if (min..=max).contains(&value) {
+            Ok(())
+        } else {
+            Err(format!("{name} must be between {min} and {max}."))
+        }
+    };
+    in_range(
+        "PNG optimization level",
+        profile.png_optimization_level.into(),
+        0.0,
+        6.0,
+    )?;
+    in_range("PNG colors", profile.png_colors.into(), 2.0, 256.0)?;
+    in_range("PNG dithering", profile.png_dithering, 0.0, 1.0)?;
+    in_range("WebP method", profile.webp_method.into(), 0.0, 6.0)?;
+    in_range(
+        "WebP near-lossless",
+        profile.webp_near_lossless.into(),
+        0.0,
+        100.0,
+    )?;
+    in_range("AVIF speed", profile.avif_speed.into(), 1.0, 10.0)?;
+    in_range(
+        "AVIF alpha quality",
+        profile.avif_alpha_quality.into(),
+        1.0,
+        100.0,
+    )
+}
+
 #[tauri::command]
 #[specta::specta]
 pub async fn reset_settings(app: tauri::AppHandle) -> Result<(), String> {
@@ -320,16 +595,55 @@ pub fn get_settings_data(app: &tauri::AppHandle) -> Result<(SettingsData, Vec<St
         }
     };
 
+    let mut value = value;
+    let migrated = migrate_profiles(&mut value);
     check_missing_keys(&value, &mut warnings);
 
     let settings: SettingsData =
         serde_json::from_value(value).map_err(|err| format!("Failed to load settings: {err}"))?;
-    if !warnings.is_empty() {
+    if migrated || !warnings.is_empty() {
         store.set(SETTINGS_KEY, json!(settings));
     }
     Ok((settings, warnings))
 }
 
+/// Carry profiles saved by older versions over to renamed settings and out
+/// of combinations that are no longer allowed.
+/// Returns whether anything changed.
+pub fn migrate_profiles(value: &mut serde_json::Value) -> bool {
+    let Some(profiles) = value.get_mut("profiles").and_then(|p| p.as_array_mut()) else {
+        return false;
+    };
+    let mut migrated = false;
+    for profile in profiles.iter_mut().filter_map(|p| p.as_object_mut()) {
+        // `keep_metadata` kept or stripped every kind at once
+        if let Some(keep) = profile.remove("keep_metadata") {
+            let (policy, color_profile) = match keep.as_bool() {
+                Some(false) => (MetadataPolicy::none(), ColorProfilePolicy::Strip),
+                _ => (MetadataPolicy::all(), ColorProfilePolicy::Keep),
+            };
+            profile.entry("metadata").or_insert(json!(policy));
+            profile
+                .entry("color_profile")
+                .or_insert(json!(color_profile));
+            migrated = true;
+        }
+        // Profiles from before the cache keep processing every image until
+        // it's turned on
+        if !profile.contains_key("should_cache") {
+            profile.insert("should_cache".to_string(), json!(false));
+            migrated = true;
+        }
+        // Both targets used to be allowed, and the size target won
+        let enabled = |key: &str| profile.get(key).and_then(|v| v.as_bool()) == Some(true);
+        if enabled("should_target_size") && enabled("should_target_similarity") {
+            profile.insert("should_target_similarity".to_string(), json!(false));
+            migrated = true;
+        }
+    }
+    migrated
+}
+
 pub fn check_missing_keys(value: &serde_json::Value, warnings: &mut Vec<String>) {
     let obj = match value.as_object() {
         Some(o) => o,
@@ -382,3 +696,55 @@ fn set_settings_data(app: &tauri::AppHandle, settings: SettingsData) {
         log::error!("Failed to emit settings changed event: {err}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_conflicting_profile_settings() {
+        let mut profile = ProfileData::new();
+        profile.should_target_similarity = true;
+        assert!(validate_profile(&profile).is_ok());
+
+        profile.should_convert = true;
+        profile.convert_extension = ImageType::AVIF;
+        assert!(validate_profile(&profile).is_err());
+        profile.convert_mode = ConvertMode::Smallest;
+        profile.convert_formats = vec![ImageType::WEBP, ImageType::AVIF];
+        assert!(validate_profile(&profile).is_err());
+        profile.convert_formats = vec![ImageType::WEBP];
+        assert!(validate_profile(&profile).is_ok());
+
+        profile.webp_near_lossless = 60;
+        assert!(validate_profile(&profile).is_err());
+        profile.enable_lossy = false;
+        assert!(validate_profile(&profile).is_ok());
+
+        profile.should_target_size = true;
+        assert!(validate_profile(&profile).is_err());
+        profile.should_target_similarity = false;
+        assert!(validate_profile(&profile).is_ok());
+    }
+
+    #[test]
+    fn keeps_size_target_of_profiles_with_both() {
+        let mut value = json!({
+            "profiles": [{"should_target_size": true, "should_target_similarity": true}]
+        });
+        assert!(migrate_profiles(&mut value));
+        assert_eq!(
+            value["profiles"][0]["should_target_similarity"],
+            json!(false)
+        );
+        assert!(!migrate_profiles(&mut value));
+    }
+
+    #[test]
+    fn leaves_cache_off_for_older_profiles() {
+        let mut value = json!({ "profiles": [{ "name": "Old" }] });
+        assert!(migrate_profiles(&mut value));
+        assert_eq!(value["profiles"][0]["should_cache"], json!(false));
+        assert!(ProfileData::new().should_cache);
+    }
+}
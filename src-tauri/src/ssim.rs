@@ -0,0 +1,93 @@
+use image::{DynamicImage, GenericImageView, GrayImage};
+
+const WINDOW: u32 = 8;
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Mean structural similarity of two images, from 0.0 (unrelated) to 1.0
+/// (identical). Compares luma in 8x8 windows, which is where compression
+/// artifacts like ringing around text and blocking show up. Images with
+/// different dimensions score 0.
+pub fn ssim(a: &DynamicImage, b: &DynamicImage) -> f64 {
+    if a.dimensions() != b.dimensions() {
+        return 0.0;
+    }
+    let a = a.to_luma8();
+    let b = b.to_luma8();
+    let (width, height) = a.dimensions();
+
+    let mut total = 0.0;
+    let mut windows = 0_u32;
+    for y in (0..height).step_by(WINDOW as usize) {
+        for x in (0..width).step_by(WINDOW as usize) {
+            let w = WINDOW.min(width - x);
+            let h = WINDOW.min(height - y);
+            total += window_ssim(&a, &b, x, y, w, h);
+            windows += 1;
+        }
+    }
+    if windows == 0 {
+        return 1.0;
+    }
+    total / windows as f64
+}
+
+fn window_ssim(a: &GrayImage, b: &GrayImage, x: u32, y: u32, w: u32, h: u32) -> f64 {
+    let n = (w * h) as f64;
+    let (mut sum_a, mut sum_b) = (0.0, 0.0);
+    let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+    for j in y..y + h {
+        for i in x..x + w {
+            let pa = a.get_pixel(i, j).0[0] as f64;
+            let pb = b.get_pixel(i, j).0[0] as f64;
+            sum_a += pa;
+            sum_b += pb;
+            sum_aa += pa * pa;
+            sum_bb += pb * pb;
+            sum_ab += pa * pb;
+        }
+    }
+    let mean_a = sum_a / n;
+    let mean_b = sum_b / n;
+    let var_a = sum_aa / n - mean_a * mean_a;
+    let var_b = sum_bb / n - mean_b * mean_b;
+    let covariance = sum_ab / n - mean_a * mean_b;
+
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([((x * 7 + y * 3) % 256) as u8])
+        }))
+    }
+
+    #[test]
+    fn identical_images_score_one() {
+        let img = gradient(37, 21);
+        assert!((ssim(&img, &img) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn different_images_score_lower() {
+        let img = gradient(32, 32);
+        let mut noisy = img.to_luma8();
+        for (x, y, pixel) in noisy.enumerate_pixels_mut() {
+            if (x + y) % 2 == 0 {
+                pixel.0[0] = pixel.0[0].wrapping_add(60);
+            }
+        }
+        let score = ssim(&img, &DynamicImage::ImageLuma8(noisy));
+        assert!(score < 0.9, "score was {score}");
+    }
+
+    #[test]
+    fn mismatched_dimensions_score_zero() {
+        assert_eq!(ssim(&gradient(8, 8), &gradient(8, 9)), 0.0);
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A parsed output filename template such as
+/// `{name}_{width}x{height}_q{quality}.{ext}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Name,
+    Ext,
+    Profile,
+    Width,
+    Height,
+    Quality,
+    Hash8,
+    Date(String),
+    Taken(String),
+}
+
+/// What a template can refer to. `encoded` is `None` until the output has
+/// been encoded, so only templates that don't need it can be rendered early.
+pub struct TemplateValues<'a> {
+    pub name: &'a str,
+    pub ext: &'a str,
+    pub profile: &'a str,
+    pub date: NaiveDateTime,
+    pub taken: NaiveDateTime,
+    pub encoded: Option<&'a Encoded<'a>>,
+}
+
+pub struct Encoded<'a> {
+    pub data: &'a [u8],
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub quality: Option<u32>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        if template.trim().is_empty() {
+            return Err("Filename template is empty.".to_string());
+        }
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            let Some(open) = rest.find(['{', '}']) else {
+                parts.push(literal(rest)?);
+                break;
+            };
+            if open > 0 {
+                parts.push(literal(&rest[..open])?);
+            }
+            if rest[open..].starts_with('}') {
+                return Err("Filename template has a \"}\" without a matching \"{\".".to_string());
+            }
+            let Some(close) = rest[open..].find('}') else {
+                return Err("Filename template has a \"{\" without a matching \"}\".".to_string());
+            };
+            parts.push(token(&rest[open + 1..open + close])?);
+            rest = &rest[open + close + 1..];
+        }
+        Ok(Self { parts })
+    }
+
+    /// Render the file name, or `None` when the template refers to the
+    /// encoded output and `values.encoded` isn't known yet.
+    pub fn render(&self, values: &TemplateValues) -> Option<String> {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Name => out.push_str(values.name),
+                Part::Ext => out.push_str(values.ext),
+                Part::Profile => out.push_str(&sanitize(values.profile)),
+                Part::Width => out.push_str(&number(values.encoded?.width)),
+                Part::Height => out.push_str(&number(values.encoded?.height)),
+                Part::Quality => out.push_str(&number(values.encoded?.quality)),
+                Part::Hash8 => {
+                    let digest = Sha256::digest(values.encoded?.data);
+                    for byte in &digest[..4] {
+                        out.push_str(&format!("{byte:02x}"));
+                    }
+                }
+                Part::Date(format) => out.push_str(&values.date.format(format).to_string()),
+                Part::Taken(format) => out.push_str(&values.taken.format(format).to_string()),
+            }
+        }
+        Some(out)
+    }
+}
+
+fn literal(text: &str) -> Result<Part, String> {
+    if text.contains('/') {
+        return Err("Filename template can't contain \"/\".".to_string());
+    }
+    Ok(Part::Literal(text.to_string()))
+}
+
+fn token(token: &str) -> Result<Part, String> {
+    let (name, format) = match token.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (token, None),
+    };
+    let part = match name {
+        "name" => Part::Name,
+        "ext" => Part::Ext,
+        "profile" => Part::Profile,
+        "width" => Part::Width,
+        "height" => Part::Height,
+        "quality" => Part::Quality,
+        "hash8" => Part::Hash8,
+        "date" => Part::Date(date_format(format)?),
+        "taken" => Part::Taken(date_format(format)?),
+        _ => return Err(format!("Unknown filename template token \"{{{token}}}\".")),
+    };
+    if format.is_some() && !matches!(part, Part::Date(_) | Part::Taken(_)) {
+        return Err(format!("\"{{{name}}}\" doesn't take a format."));
+    }
+    Ok(part)
+}
+
+fn date_format(format: Option<&str>) -> Result<String, String> {
+    let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+    if format.contains('/') {
+        return Err("Filename template can't contain \"/\".".to_string());
+    }
+    // chrono panics when rendering a format it can't write, like an invalid
+    // specifier or a time zone the dates don't have, so try a sample first
+    let mut sample = String::new();
+    if format.is_empty() || write!(sample, "{}", NaiveDateTime::default().format(format)).is_err() {
+        return Err(format!("Invalid date format \"{format}\"."));
+    }
+    Ok(format.to_string())
+}
+
+fn number(value: Option<u32>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Profile names are free text, so keep them from adding folders
+fn sanitize(text: &str) -> String {
+    text.replace(['/', ':'], "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn values<'a>(encoded: Option<&'a Encoded<'a>>) -> TemplateValues<'a> {
+        TemplateValues {
+            name: "product",
+            ext: "webp",
+            profile: "Web/Shop",
+            date: NaiveDate::from_ymd_opt(2025, 3, 9)
+                .unwrap()
+                .and_hms_opt(14, 5, 0)
+                .unwrap(),
+            taken: NaiveDate::from_ymd_opt(2021, 12, 31)
+                .unwrap()
+                .and_hms_opt(8, 0, 0)
+                .unwrap(),
+            encoded,
+        }
+    }
+
+    #[test]
+    fn renders_tokens() {
+        let encoded = Encoded {
+            data: b"hello",
+            width: Some(1200),
+            height: Some(800),
+            quality: Some(75),
+        };
+        let render = |template: &str| {
+            Template::parse(template)
+                .unwrap()
+                .render(&values(Some(&encoded)))
+        };
+
+        assert_eq!(
+            render("{name}_{width}x{height}_q{quality}.{ext}"),
+            Some("product_1200x800_q75.webp".to_string())
+        );
+        assert_eq!(
+            render("{profile}-{hash8}.{ext}"),
+            Some("Web-Shop-2cf24dba.webp".to_string())
+        );
+        assert_eq!(
+            render("{date}_{taken:%Y%m%d-%H%M}.{ext}"),
+            Some("2025-03-09_20211231-0800.webp".to_string())
+        );
+    }
+
+    #[test]
+    fn needs_encoded_output() {
+        let template = Template::parse("{name}_{width}.{ext}").unwrap();
+        assert_eq!(template.render(&values(None)), None);
+
+        let template = Template::parse("{name}.min.{ext}").unwrap();
+        assert_eq!(
+            template.render(&values(None)),
+            Some("product.min.webp".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!(Template::parse("").is_err());
+        assert!(Template::parse("{name").is_err());
+        assert!(Template::parse("name}").is_err());
+        assert!(Template::parse("{size}.{ext}").is_err());
+        assert!(Template::parse("{width:%Y}.{ext}").is_err());
+        assert!(Template::parse("{date:%Q}.{ext}").is_err());
+        assert!(Template::parse("{date:%Y%z}.{ext}").is_err());
+        assert!(Template::parse("{taken:%Z}.{ext}").is_err());
+        assert!(Template::parse("out/{name}.{ext}").is_err());
+        assert!(Template::parse("{date:%Y/%m}.{ext}").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-import { open } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import { FaSolidXmark } from "solid-icons/fa";
 import { VsAdd, VsSettings } from "solid-icons/vs";
 import { type JSXElement, Show } from "solid-js";
@@ -14,7 +14,14 @@ import {
   setProfileActive,
   settings,
 } from "./settings/settingsData";
-import { addFile, clearFiles, store } from "./store";
+import {
+  addFile,
+  clearFiles,
+  isFileDone,
+  setPaused,
+  store,
+} from "./store";
+import { addToast } from "./Toast";
 import { toHumanReadableSize } from "./utils";
 
 openFileDialogListener(() => {
@@ -81,6 +88,8 @@ export default function BottomBar() {
         options={options().map((e) => e.label)}
       />
       <SettingsButton />
+      <ReportButton />
+      <PauseButton />
       <ClearButton />
     </div>
   );
@@ -96,6 +105,38 @@ function AddButton() {
   );
 }
 
+function PauseButton() {
+  return (
+    <Button onClick={() => setPaused(!store.paused)}>
+      <span class="flex items-center gap-1 px-2 text-sm">
+        {store.paused ? "Resume" : "Pause"}
+      </span>
+    </Button>
+  );
+}
+
+async function exportReport() {
+  const path = await save({
+    defaultPath: "alic-report.csv",
+    filters: [{ name: "Report", extensions: ["csv", "json"] }],
+  });
+  if (!path) {
+    return;
+  }
+  const result = await commands.exportReport(path);
+  if (result.status === "error") {
+    addToast({ message: result.error, type: "error" });
+  }
+}
+
+function ReportButton() {
+  return (
+    <Button onClick={exportReport} disabled={!store.files.some(isFileDone)}>
+      <span class="flex items-center gap-1 px-2 text-sm">Report</span>
+    </Button>
+  );
+}
+
 function ClearButton() {
   return (
     <Button onClick={clearFiles} disabled={store.files.length === 0}>
@@ -7,8 +7,8 @@ import { commands } from "./bindings";
 import { addFileListener } from "./listeners";
 import { addFile } from "./store";
 
-addFileListener((path) => {
-  addFile(path);
+addFileListener((path, root) => {
+  addFile(path, root);
 });
 
 export default function Dropper() {
@@ -20,7 +20,7 @@ import {
 } from "solid-js";
 import type { FileEntry, FileEntryStatus } from "./bindings";
 import { commands } from "./bindings";
-import { removeFile, store } from "./store";
+import { prioritizeFile, removeFile, retryFile, store } from "./store";
 import { testStore } from "./testdata";
 import { toHumanReadableSize } from "./utils";
 
@@ -84,6 +84,18 @@ function MyTable() {
       }
     }
 
+    // Retry failed files with R, and process queued ones next with P
+    if (e.key === "r" && !e.metaKey && !e.ctrlKey) {
+      for (const path of selectedFiles()) {
+        retryFile(path);
+      }
+    }
+    if (e.key === "p" && !e.metaKey && !e.ctrlKey) {
+      for (const path of selectedFiles()) {
+        prioritizeFile(path);
+      }
+    }
+
     // Escape to clear selection
     if (e.key === "Escape") {
       setSelectedFiles(new Set<string>());
@@ -269,7 +281,10 @@ function MyTable() {
                     </Tooltip.Trigger>
                     <Tooltip.Portal>
                       <Tooltip.Content class="border border-accent bg-secondary px-2 py-1">
-                        <Show when={file.error} fallback={file.status}>
+                        <Show
+                          when={file.error}
+                          fallback={store.progress[file.path] ?? file.status}
+                        >
                           {file.error}
                         </Show>
                       </Tooltip.Content>
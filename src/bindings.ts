@@ -7,9 +7,21 @@ import * as __TAURI_EVENT from "@tauri-apps/api/event";
 export const commands = {
 	openSettingsWindow: (path: string | null) => typedError<null, string>(__TAURI_INVOKE("open_settings_window", { path })),
 	openLinkInBrowser: (url: string) => typedError<null, string>(__TAURI_INVOKE("open_link_in_browser", { url })),
-	processImg: (parameters: ProfileData_Deserialize, file: FileEntry, parallelImages: number) => typedError<CompressResult, AlicError>(__TAURI_INVOKE("process_img", { parameters, file, parallelImages })),
 	getFileInfo: (path: string) => typedError<FileInfoResult, string>(__TAURI_INVOKE("get_file_info", { path })),
 	getAllImages: (path: string) => typedError<null, string>(__TAURI_INVOKE("get_all_images", { path })),
+	clearBatch: () => __TAURI_INVOKE<void>("clear_batch"),
+	enqueueFile: (path: string, root: string | null, profile: ProfileData_Deserialize) => typedError<QueuedFile, string>(__TAURI_INVOKE("enqueue_file", { path, root, profile })),
+	getQueue: () => typedError<QueueState, string>(__TAURI_INVOKE("get_queue")),
+	startQueue: () => typedError<null, string>(__TAURI_INVOKE("start_queue")),
+	setQueuePaused: (paused: boolean) => typedError<null, string>(__TAURI_INVOKE("set_queue_paused", { paused })),
+	setJobPriority: (jobId: number, priority: number) => typedError<null, string>(__TAURI_INVOKE("set_job_priority", { jobId, priority })),
+	retryJob: (jobId: number) => typedError<null, string>(__TAURI_INVOKE("retry_job", { jobId })),
+	cancelJob: (jobId: number) => __TAURI_INVOKE<boolean>("cancel_job", { jobId }),
+	clearQueue: () => __TAURI_INVOKE<void>("clear_queue"),
+	getHistory: (limit: number) => __TAURI_INVOKE<HistoryBatch[]>("get_history", { limit }),
+	restoreBatch: (batch: number) => typedError<string[], string>(__TAURI_INVOKE("restore_batch", { batch })),
+	restoreFiles: (trashedPaths: string[]) => typedError<string[], string>(__TAURI_INVOKE("restore_files", { trashedPaths })),
+	exportReport: (path: string) => typedError<null, string>(__TAURI_INVOKE("export_report", { path })),
 	getSettings: () => typedError<SettingsResult_Serialize, string>(__TAURI_INVOKE("get_settings")),
 	saveSettings: (settings: SettingsData_Deserialize) => typedError<null, string>(__TAURI_INVOKE("save_settings", { settings })),
 	resetSettings: () => typedError<null, string>(__TAURI_INVOKE("reset_settings")),
@@ -29,30 +41,55 @@ export const events = {
 	addFileEvent: makeEvent<AddFileEvent>("add-file-event"),
 	clearFilesEvent: makeEvent<ClearFilesEvent>("clear-files-event"),
 	errorEvent: makeEvent<ErrorEvent>("error-event"),
+	jobProgressEvent: makeEvent<JobProgressEvent>("job-progress-event"),
+	jobResultEvent: makeEvent<JobResultEvent>("job-result-event"),
 	openAddFileDialogEvent: makeEvent<OpenAddFileDialogEvent>("open-add-file-dialog-event"),
 	settingsChangedEvent: makeEvent<SettingsChangedEvent>("settings-changed-event"),
 	updateStateEvent: makeEvent<UpdateStateEvent>("update-state-event"),
 };
 
 /* Types */
-export type AddFileEvent = string;
+/**  A file to queue, with the folder it was found in when a folder was added. */
+export type AddFileEvent = {
+	path: string,
+	root: string | null,
+};
 
 export type AlicError = {
 	error: string,
 	errorType: AlicErrorType,
 };
 
-export type AlicErrorType = "Unknown" | "FileTooLarge" | "FileNotFound" | "UnsupportedFileType" | "WontOverwrite" | "NotSmaller" | "ImageResizeError" | "InvalidHexColor";
+export type AlicErrorType = "Unknown" | "FileTooLarge" | "FileNotFound" | "UnsupportedFileType" | "WontOverwrite" | "NotSmaller" | "ImageResizeError" | "InvalidHexColor" | "TargetSizeNotMet" | "OutputExists" | "Skipped" | "Cancelled";
+
+/**  JPEG chroma subsampling. */
+export type ChromaSubsampling = "Auto" | "CS444" | "CS422" | "CS420";
 
 export type ClearFilesEvent = null;
 
+/**  What to do when something already exists at an output path. */
+export type CollisionPolicy = "Replace" | "Skip" | "Rename" | "Error";
+
+/**  What happens to the source's ICC color profile. */
+export type ColorProfilePolicy = "Keep" | "ConvertToSrgb" | "Strip";
+
 export type CompressResult = {
 	path: string,
 	outSize: number,
 	outPath: string,
 	result: string,
+	quality: number | null,
+	outputs: OutputFile[],
+	snippet: string | null,
+	originalSize: number,
+	originalFormat: ImageType,
+	originalWidth: number | null,
+	originalHeight: number | null,
 };
 
+/**  How `should_convert` picks the output format. */
+export type ConvertMode = "Single" | "Smallest" | "All";
+
 /**  A backend error the frontend should surface as a toast. */
 export type ErrorEvent = string;
 
@@ -65,6 +102,7 @@ export type FileEntry = {
 	ext: string | null,
 	savings: number | null,
 	error: string | null,
+	root: string | null,
 };
 
 export type FileEntryStatus = "Processing" | "Compressing" | "Complete" | "AlreadySmaller" | "Error";
@@ -75,10 +113,64 @@ export type FileInfoResult = {
 	filename: string,
 };
 
-export type ImageType = "JPEG" | "PNG" | "WEBP" | "GIF" | "TIFF" | "AVIF";
+/**  The files trashed in one batch that haven't been restored, oldest first. */
+export type HistoryBatch = {
+	id: number,
+	files: TrashedFile[],
+};
+
+export type ImageType = "JPEG" | "PNG" | "WEBP" | "GIF" | "TIFF" | "AVIF" | "JXL" | "HEIC";
+
+/**  A compression job moving on to another stage. */
+export type JobProgressEvent = {
+	jobId: number,
+	stage: JobStage,
+	elapsedMs: number,
+	previousStageMs: number,
+};
+
+export type JobStage = { type: "Queued" } | { type: "Reading" } | { type: "Resizing" } | { type: "Encoding"; output: number; outputs: number } | { type: "Writing" } | { type: "Done" };
+
+/**  A queued file done processing, with its result or error. */
+export type JobResultEvent = {
+	jobId: number,
+	path: string,
+	result: CompressResult | null,
+	error: AlicError | null,
+};
+
+export type MetadataPolicy = MetadataPolicy_Serialize | MetadataPolicy_Deserialize;
+
+export type MetadataPolicy_Deserialize = {
+	gps?: boolean,
+	camera?: boolean,
+	copyright?: boolean,
+	thumbnail?: boolean,
+	orientation?: boolean,
+};
+
+export type MetadataPolicy_Serialize = {
+	gps: boolean,
+	camera: boolean,
+	copyright: boolean,
+	thumbnail: boolean,
+	orientation: boolean,
+};
+
+/**  What to do with an output that doesn't save `min_savings`. */
+export type NotSmallerPolicy = "Skip" | "CopyOriginal" | "Write";
 
 export type OpenAddFileDialogEvent = null;
 
+export type OutputFile = {
+	path: string,
+	size: number,
+	format: ImageType,
+	quality: number | null,
+	width: number | null,
+	height: number | null,
+};
+
 export type ProfileData = ProfileData_Serialize | ProfileData_Deserialize;
 
 export type ProfileData_Deserialize = {
@@ -90,7 +182,8 @@ export type ProfileData_Deserialize = {
 	should_overwrite?: boolean,
 	enable_lossy?: boolean,
 	keep_timestamps?: boolean,
-	keep_metadata?: boolean,
+	metadata?: MetadataPolicy_Deserialize,
+	color_profile?: ColorProfilePolicy,
 	should_background_fill?: boolean,
 	background_fill?: string,
 	convert_extension?: ImageType,
@@ -102,6 +195,37 @@ export type ProfileData_Deserialize = {
 	webp_quality?: number,
 	gif_quality?: number,
 	avif_quality?: number,
+	jxl_quality?: number,
+	should_target_size?: boolean,
+	target_size_kb?: number,
+	should_target_similarity?: boolean,
+	target_similarity?: number,
+	convert_mode?: ConvertMode,
+	convert_formats?: ImageType[],
+	should_responsive?: boolean,
+	responsive_widths?: number[],
+	should_output_dir?: boolean,
+	output_dir?: string,
+	flatten_output?: boolean,
+	should_template?: boolean,
+	filename_template?: string,
+	on_collision?: CollisionPolicy,
+	should_cache?: boolean,
+	min_savings?: number,
+	min_savings_unit?: SavingsUnit,
+	not_smaller?: NotSmallerPolicy,
+	should_mark_outputs?: boolean,
+	jpeg_progressive?: boolean,
+	jpeg_chroma_subsampling?: ChromaSubsampling,
+	png_optimization_level?: number,
+	png_colors?: number,
+	png_dithering?: number,
+	webp_method?: number,
+	webp_near_lossless?: number,
+	avif_speed?: number,
+	should_avif_alpha_quality?: boolean,
+	avif_alpha_quality?: number,
+	jxl_lossless_jpeg?: boolean,
 } & {
 	add_postfix?: boolean,
 } | {
@@ -117,7 +241,8 @@ export type ProfileData_Serialize = {
 	should_overwrite: boolean,
 	enable_lossy: boolean,
 	keep_timestamps: boolean,
-	keep_metadata: boolean,
+	metadata: MetadataPolicy_Serialize,
+	color_profile: ColorProfilePolicy,
 	add_postfix: boolean,
 	should_background_fill: boolean,
 	background_fill: string,
@@ -130,8 +255,63 @@ export type ProfileData_Serialize = {
 	webp_quality: number,
 	gif_quality: number,
 	avif_quality: number,
+	jxl_quality: number,
+	should_target_size: boolean,
+	target_size_kb: number,
+	should_target_similarity: boolean,
+	target_similarity: number,
+	convert_mode: ConvertMode,
+	convert_formats: ImageType[],
+	should_responsive: boolean,
+	responsive_widths: number[],
+	should_output_dir: boolean,
+	output_dir: string,
+	flatten_output: boolean,
+	should_template: boolean,
+	filename_template: string,
+	on_collision: CollisionPolicy,
+	should_cache: boolean,
+	min_savings: number,
+	min_savings_unit: SavingsUnit,
+	not_smaller: NotSmallerPolicy,
+	should_mark_outputs: boolean,
+	jpeg_progressive: boolean,
+	jpeg_chroma_subsampling: ChromaSubsampling,
+	png_optimization_level: number,
+	png_colors: number,
+	png_dithering: number,
+	webp_method: number,
+	webp_near_lossless: number,
+	avif_speed: number,
+	should_avif_alpha_quality: boolean,
+	avif_alpha_quality: number,
+	jxl_lossless_jpeg: boolean,
 };
 
+export type QueueState = {
+	paused: boolean,
+	files: QueuedFile[],
+};
+
+export type QueueStatus = "Queued" | "Running" | "Failed";
+
+/**
+ *  A file waiting in the queue, being processed, or failed and kept around
+ *  so it can be retried.
+ */
+export type QueuedFile = {
+	jobId: number,
+	path: string,
+	root: string | null,
+	profileId: number,
+	priority: number,
+	status: QueueStatus,
+	attempts: number,
+};
+
+/**  How `min_savings` is measured against the original's size. */
+export type SavingsUnit = "Percent" | "Bytes";
+
 export type SettingsChangedEvent = null;
 
 export type SettingsData = SettingsData_Serialize | SettingsData_Deserialize;
@@ -166,6 +346,15 @@ export type SettingsResult_Serialize = {
 
 export type ThemeKind = "Light" | "Dark" | "System";
 
+/**  A file moved to the trash to make room for an output. */
+export type TrashedFile = {
+	batch: number,
+	originalPath: string,
+	outputPath: string,
+	trashedPath: string,
+	trashedAt: number,
+};
+
 export type UpdateStateEvent = { type: "CheckingForUpdate"; message: string } | { type: "NoUpdate"; message: string } | { type: "Error"; message: string } | { type: "Downloading"; percent: number | null; bytes_downloaded: number | null; total_bytes: number | null } | { type: "Success"; version: string; release_notes: string | null };
 
 /* Tauri Specta runtime */
@@ -1,3 +1,3 @@
-const FILE_TYPES = ["png", "jpeg", "jpg", "gif", "webp", "tiff", "tif", "avif"];
+const FILE_TYPES = ["png", "jpeg", "jpg", "gif", "webp", "tiff", "tif", "avif", "jxl", "heic", "heif"];
 
 export { FILE_TYPES };
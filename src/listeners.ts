@@ -1,4 +1,9 @@
-import { events, type UpdateStateEvent } from "./bindings";
+import {
+  events,
+  type JobProgressEvent,
+  type JobResultEvent,
+  type UpdateStateEvent,
+} from "./bindings";
 
 function openFileDialogListener(cb: () => void) {
   return events.openAddFileDialogEvent.listen(() => {
@@ -6,9 +11,9 @@ function openFileDialogListener(cb: () => void) {
   });
 }
 
-function addFileListener(cb: (path: string) => void) {
+function addFileListener(cb: (path: string, root: string | null) => void) {
   return events.addFileEvent.listen((event) => {
-    cb(event.payload);
+    cb(event.payload.path, event.payload.root);
   });
 }
 
@@ -24,6 +29,18 @@ function clearFilesListener(cb: () => void) {
   });
 }
 
+function jobProgressListener(cb: (progress: JobProgressEvent) => void) {
+  return events.jobProgressEvent.listen((event) => {
+    cb(event.payload);
+  });
+}
+
+function jobResultListener(cb: (result: JobResultEvent) => void) {
+  return events.jobResultEvent.listen((event) => {
+    cb(event.payload);
+  });
+}
+
 function settingsChangedListener(cb: () => void) {
   return events.settingsChangedEvent.listen(() => {
     cb();
@@ -41,6 +58,8 @@ export {
   addFileListener,
   clearFilesListener,
   errorListener,
+  jobProgressListener,
+  jobResultListener,
   settingsChangedListener,
   updateResultListener,
 };
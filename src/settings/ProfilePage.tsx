@@ -1,6 +1,16 @@
 import { useNavigate, useParams } from "@solidjs/router";
-import { createEffect } from "solid-js";
-import { commands, type ImageType } from "../bindings";
+import { createEffect, For } from "solid-js";
+import {
+  type ChromaSubsampling,
+  type CollisionPolicy,
+  type ColorProfilePolicy,
+  type ConvertMode,
+  commands,
+  type ImageType,
+  type MetadataPolicy_Serialize,
+  type NotSmallerPolicy,
+  type SavingsUnit,
+} from "../bindings";
 import { confirmModal } from "./ConfirmModal";
 import {
   SettingBox,
@@ -15,7 +25,70 @@ import {
 } from "./SettingsUI";
 import { deleteProfile, settings, updateProfile } from "./settingsData";
 
-const imageTypes: ImageType[] = ["JPEG", "PNG", "WEBP", "GIF", "TIFF", "AVIF"];
+const imageTypes: ImageType[] = [
+  "JPEG",
+  "PNG",
+  "WEBP",
+  "GIF",
+  "TIFF",
+  "AVIF",
+  "JXL",
+];
+const convertModes: ConvertMode[] = ["Single", "Smallest", "All"];
+const chromaSubsamplings: ChromaSubsampling[] = [
+  "Auto",
+  "CS444",
+  "CS422",
+  "CS420",
+];
+const collisionPolicies: CollisionPolicy[] = [
+  "Replace",
+  "Skip",
+  "Rename",
+  "Error",
+];
+const savingsUnits: SavingsUnit[] = ["Percent", "Bytes"];
+const notSmallerPolicies: NotSmallerPolicy[] = ["Skip", "CopyOriginal", "Write"];
+const colorProfilePolicies: ColorProfilePolicy[] = [
+  "Keep",
+  "ConvertToSrgb",
+  "Strip",
+];
+const metadataKinds: {
+  key: keyof MetadataPolicy_Serialize;
+  title: string;
+  helpText: string;
+}[] = [
+  {
+    key: "gps",
+    title: "Keep Location",
+    helpText:
+      "GPS coordinates of where the photo was taken. Disable to remove them before publishing.",
+  },
+  {
+    key: "camera",
+    title: "Keep Camera Data",
+    helpText:
+      "Camera make and model, exposure settings, capture date and other EXIF data.",
+  },
+  {
+    key: "copyright",
+    title: "Keep Copyright",
+    helpText: "Artist and copyright fields, and IPTC and XMP data.",
+  },
+  {
+    key: "thumbnail",
+    title: "Keep Thumbnail",
+    helpText:
+      "The small preview some cameras embed, which can still show what was cropped out.",
+  },
+  {
+    key: "orientation",
+    title: "Keep Orientation",
+    helpText:
+      "Keep the orientation flag when possible. Disable to rotate the pixels instead.",
+  },
+];
 
 function ProfilePage() {
   const navigate = useNavigate();
@@ -92,6 +165,219 @@ function ProfilePage() {
             }}
           />
         </SettingRow>
+        <SettingRow title="JXL Quality">
+          <QualitySlider
+            disabled={!lossy()}
+            value={data().jxl_quality ?? 80}
+            onChange={(value) => {
+              updateProfile(data().id, { jxl_quality: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Target File Size"
+          helpText="Ignore the quality settings above and pick the highest quality that keeps each image under the maximum size. Turns off Target Similarity."
+        >
+          <SettingsToggle
+            value={data().should_target_size ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_target_size: value,
+                should_target_similarity: value
+                  ? false
+                  : data().should_target_similarity,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow title="Maximum Size">
+          <SettingsNumberInput
+            min={1}
+            value={data().target_size_kb ?? 500}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                target_size_kb: value,
+              });
+            }}
+          />
+          <span class="pl-2">kB</span>
+        </SettingRow>
+        <SettingRow
+          title="Target Similarity"
+          helpText="Ignore the quality settings above and pick the lowest quality that still looks this similar to the original (SSIM, 0 to 1). Text and screenshots get more quality than photos. Turns off Target File Size. Not available for AVIF output."
+        >
+          <SettingsToggle
+            value={data().should_target_similarity ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_target_similarity: value,
+                should_target_size: value ? false : data().should_target_size,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow title="Minimum Similarity">
+          <input
+            class="w-20 rounded-md border-0 bg-secondary py-1.5 shadow-sm sm:text-sm/6"
+            type="number"
+            min="0.5"
+            max="1"
+            step="0.005"
+            value={data().target_similarity ?? 0.98}
+            onInput={(e) => {
+              const value = Number.parseFloat(e.target.value);
+              if (Number.isNaN(value) || value <= 0 || value > 1) {
+                return;
+              }
+              updateProfile(data().id, { target_similarity: value });
+            }}
+          />
+        </SettingRow>
+      </SettingBox>
+      <div class="pt-8" />
+      <SettingBox title="Encoders">
+        <SettingRow
+          title="Progressive JPEG"
+          helpText="Progressive JPEGs load in passes from blurry to sharp and are usually a little smaller. Turn off for baseline JPEGs, which some older software expects."
+        >
+          <SettingsToggle
+            value={data().jpeg_progressive ?? true}
+            onChange={(value) => {
+              updateProfile(data().id, { jpeg_progressive: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="JPEG Chroma Subsampling"
+          helpText="How much color detail to keep. CS444 keeps all of it, CS422 halves it horizontally and CS420 halves it in both directions for smaller files. Auto picks based on quality."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={data().jpeg_chroma_subsampling ?? "Auto"}
+            onChange={(value) =>
+              updateProfile(data().id, {
+                jpeg_chroma_subsampling: value as ChromaSubsampling,
+              })
+            }
+            options={chromaSubsamplings}
+          />
+        </SettingRow>
+        <SettingRow
+          title="PNG Optimization Level"
+          helpText="How hard to search for a smaller lossless encoding, from 0 (fastest) to 6 (smallest)."
+        >
+          <SettingsNumberInput
+            min={0}
+            max={6}
+            value={data().png_optimization_level ?? 3}
+            onChange={(value) => {
+              updateProfile(data().id, { png_optimization_level: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="PNG Colors"
+          helpText="The most colors a lossy PNG palette can have, from 2 to 256. Fewer colors make smaller files."
+        >
+          <SettingsNumberInput
+            min={2}
+            max={256}
+            value={data().png_colors ?? 256}
+            onChange={(value) => {
+              updateProfile(data().id, { png_colors: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="PNG Dithering"
+          helpText="How much to dither lossy PNGs to hide banding, from 0 (none) to 1 (full). Less dithering makes smaller files."
+        >
+          <input
+            class="w-20 rounded-md border-0 bg-secondary py-1.5 shadow-sm sm:text-sm/6"
+            type="number"
+            min="0"
+            max="1"
+            step="0.1"
+            value={data().png_dithering ?? 1}
+            onInput={(e) => {
+              const value = Number.parseFloat(e.target.value);
+              if (Number.isNaN(value) || value < 0 || value > 1) {
+                return;
+              }
+              updateProfile(data().id, { png_dithering: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="WEBP Method"
+          helpText="How much effort to spend on WebP files, from 0 (fastest) to 6 (smallest)."
+        >
+          <SettingsNumberInput
+            min={0}
+            max={6}
+            value={data().webp_method ?? 4}
+            onChange={(value) => {
+              updateProfile(data().id, { webp_method: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="WEBP Near-Lossless"
+          helpText="With lossy compression off, slightly adjust pixels so lossless WebPs compress better, from 0 (most) to 100 (off)."
+        >
+          <SettingsNumberInput
+            min={0}
+            max={100}
+            value={data().webp_near_lossless ?? 100}
+            onChange={(value) => {
+              updateProfile(data().id, { webp_near_lossless: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="AVIF Speed"
+          helpText="From 1 (slowest, smallest files) to 10 (fastest)."
+        >
+          <SettingsNumberInput
+            min={1}
+            max={10}
+            value={data().avif_speed ?? 4}
+            onChange={(value) => {
+              updateProfile(data().id, { avif_speed: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Separate AVIF Alpha Quality"
+          helpText="Encode transparency at its own quality instead of the AVIF quality. Soft edges and shadows may need more quality than the colors."
+        >
+          <SettingsToggle
+            value={data().should_avif_alpha_quality ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, { should_avif_alpha_quality: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow title="AVIF Alpha Quality">
+          <QualitySlider
+            disabled={!data().should_avif_alpha_quality}
+            value={data().avif_alpha_quality ?? 80}
+            onChange={(value) => {
+              updateProfile(data().id, { avif_alpha_quality: value });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Lossless JPEG to JXL"
+          helpText="Repack JPEGs converted to JXL instead of re-encoding them. Files are usually about 20% smaller with no quality loss, and the original JPEG can be restored bit for bit. Resized images and HEIC photos are re-encoded instead."
+        >
+          <SettingsToggle
+            value={data().jxl_lossless_jpeg ?? true}
+            onChange={(value) => {
+              updateProfile(data().id, { jxl_lossless_jpeg: value });
+            }}
+          />
+        </SettingRow>
       </SettingBox>
       <div class="pt-8" />
       <SettingBox title="Resize">
@@ -158,6 +444,37 @@ function ProfilePage() {
             }}
           />
         </SettingRow>
+        <SettingRow
+          title="Responsive Set"
+          helpText="Write one file per width below (image-480w.webp and so on) and return a ready-made srcset snippet. Widths larger than the image are skipped."
+        >
+          <SettingsToggle
+            value={data().should_responsive ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_responsive: value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow title="Responsive Widths">
+          <SettingsInput
+            class="w-40"
+            label="Widths"
+            placeholder="480, 960, 1920"
+            value={(data().responsive_widths ?? []).join(", ")}
+            onChange={(value) => {
+              const widths = value
+                .split(",")
+                .map((w) => Number.parseInt(w.trim(), 10))
+                .filter((w) => !Number.isNaN(w) && w > 0);
+              if (widths.length > 0) {
+                updateProfile(data().id, { responsive_widths: widths });
+              }
+            }}
+          />
+          <span class="pl-2">px</span>
+        </SettingRow>
       </SettingBox>
       <div class="pt-8" />
       <SettingBox title="Output">
@@ -174,17 +491,33 @@ function ProfilePage() {
             }}
           />
         </SettingRow>
+        <For each={metadataKinds}>
+          {(kind) => (
+            <SettingRow title={kind.title} helpText={kind.helpText}>
+              <SettingsToggle
+                value={data().metadata?.[kind.key] ?? true}
+                onChange={(value) => {
+                  updateProfile(data().id, {
+                    metadata: { ...data().metadata, [kind.key]: value },
+                  });
+                }}
+              />
+            </SettingRow>
+          )}
+        </For>
         <SettingRow
-          title="Keep Metadata"
-          helpText="Disable to remove all EXIF and color profile information. This may cause colors to change slightly, but removes potentially sensitive or identifying data from the image. For example: location data."
+          title="Color Profile"
+          helpText="Keep the ICC profile, convert colors to sRGB, or strip the profile. Formats that can't carry a profile are converted to sRGB when keeping it. Stripping it may cause colors to change."
         >
-          <SettingsToggle
-            value={data().keep_metadata ?? true}
-            onChange={(value) => {
+          <SettingsSelect
+            class="w-32"
+            value={data().color_profile ?? "Keep"}
+            onChange={(value) =>
               updateProfile(data().id, {
-                keep_metadata: value,
-              });
-            }}
+                color_profile: value as ColorProfilePolicy,
+              })
+            }
+            options={colorProfilePolicies}
           />
         </SettingRow>
         <SettingRow
@@ -225,6 +558,148 @@ function ProfilePage() {
             }}
           />
         </SettingRow>
+        <SettingRow
+          title="When Output Exists"
+          helpText="Replace moves the existing file to the trash, Skip keeps it, Rename writes name.min (2).webp instead, and Error fails the image. Two images in the same batch never replace each other's output."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={data().on_collision ?? "Replace"}
+            onChange={(policy) =>
+              updateProfile(data().id, {
+                on_collision: policy as CollisionPolicy,
+              })
+            }
+            options={collisionPolicies}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Minimum Savings"
+          helpText="How much smaller than the original an output has to be, as a percentage of the original or a number of bytes. Outputs converted to another format are always kept."
+        >
+          <SettingsNumberInput
+            min={0}
+            max={data().min_savings_unit === "Bytes" ? undefined : 100}
+            value={data().min_savings ?? 5}
+            onChange={(value) => {
+              updateProfile(data().id, { min_savings: value });
+            }}
+          />
+          <SettingsSelect
+            class="ml-2 w-24"
+            value={data().min_savings_unit ?? "Percent"}
+            onChange={(value) =>
+              updateProfile(data().id, {
+                min_savings_unit: value as SavingsUnit,
+              })
+            }
+            options={savingsUnits}
+          />
+        </SettingRow>
+        <SettingRow
+          title="When Not Smaller"
+          helpText="What to do when an output doesn't save the minimum: skip it, copy the original to the output path so an output folder has every image, or write it anyway."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={data().not_smaller ?? "Skip"}
+            onChange={(value) =>
+              updateProfile(data().id, {
+                not_smaller: value as NotSmallerPolicy,
+              })
+            }
+            options={notSmallerPolicies}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Skip Processed Images"
+          helpText="Remember images already processed with these settings and skip them next time, as long as their outputs still exist."
+        >
+          <SettingsToggle
+            value={data().should_cache ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_cache: value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Mark Outputs"
+          helpText="Add a small tag to PNG and JPEG outputs so Alic never compresses them lossily again, even on another Mac."
+        >
+          <SettingsToggle
+            value={data().should_mark_outputs ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_mark_outputs: value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Filename Template"
+          helpText="Name outputs from a template instead of the postfix. Tokens: {name}, {ext}, {width}, {height}, {quality}, {profile}, {hash8}, {date:%Y-%m-%d} and {taken:%Y-%m-%d} (EXIF capture date)."
+        >
+          <SettingsToggle
+            value={data().should_template ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_template: value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow title="Template">
+          <input
+            class="w-64 rounded-md border-0 bg-secondary py-1.5 shadow-sm sm:text-sm/6"
+            type="text"
+            value={data().filename_template ?? ""}
+            onInput={(e) => {
+              updateProfile(data().id, {
+                filename_template: e.target.value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Output Folder"
+          helpText="Write compressed images to a separate folder instead of next to the originals. Added folders keep their structure inside it."
+        >
+          <SettingsToggle
+            value={data().should_output_dir ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                should_output_dir: value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow title="Output Folder Path">
+          <input
+            class="w-48 rounded-md border-0 bg-secondary py-1.5 shadow-sm sm:text-sm/6"
+            type="text"
+            value={data().output_dir ?? ""}
+            onInput={(e) => {
+              updateProfile(data().id, {
+                output_dir: e.target.value,
+              });
+            }}
+          />
+        </SettingRow>
+        <SettingRow
+          title="Flatten Folders"
+          helpText="Put every output directly in the output folder instead of recreating the added folders' structure."
+        >
+          <SettingsToggle
+            value={data().flatten_output ?? false}
+            onChange={(value) => {
+              updateProfile(data().id, {
+                flatten_output: value,
+              });
+            }}
+          />
+        </SettingRow>
         <SettingRow
           title="Convert Image"
           helpText="Enable converting files to the specified format."
@@ -250,6 +725,29 @@ function ProfilePage() {
             options={imageTypes}
           />
         </SettingRow>
+        <SettingRow
+          title="Convert Mode"
+          helpText="Single converts to the format above. Smallest converts to every format below and keeps whichever file is smallest. All writes one file per format below, for <picture> fallbacks."
+        >
+          <SettingsSelect
+            class="w-32"
+            value={data().convert_mode ?? "Single"}
+            onChange={(mode) =>
+              updateProfile(data().id, {
+                convert_mode: mode as ConvertMode,
+              })
+            }
+            options={convertModes}
+          />
+        </SettingRow>
+        <SettingRow title="Formats">
+          <FormatChecklist
+            value={data().convert_formats ?? []}
+            onChange={(formats) => {
+              updateProfile(data().id, { convert_formats: formats });
+            }}
+          />
+        </SettingRow>
       </SettingBox>
       <div class="pt-8" />
       <SettingBox title="Manage">
@@ -323,4 +821,32 @@ function QualitySlider(props: {
   );
 }
 
+function FormatChecklist(props: {
+  value: ImageType[];
+  onChange: (value: ImageType[]) => void;
+}) {
+  return (
+    <div class="flex flex-wrap gap-3">
+      <For each={imageTypes}>
+        {(type) => (
+          <label class="flex items-center gap-1">
+            <input
+              type="checkbox"
+              checked={props.value.includes(type)}
+              onChange={(e) => {
+                // Keep the list in a stable order regardless of click order
+                const selected = imageTypes.filter((t) =>
+                  t === type ? e.target.checked : props.value.includes(t),
+                );
+                props.onChange(selected);
+              }}
+            />
+            {type}
+          </label>
+        )}
+      </For>
+    </div>
+  );
+}
+
 export { ProfilePage };
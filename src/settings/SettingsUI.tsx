@@ -194,17 +194,20 @@ function SettingsNumberInput(props: {
   value: number;
   onChange: (value: number) => void;
   min?: number;
+  max?: number;
 }) {
   const min = () => props.min ?? 0;
+  const max = () => props.max ?? Number.POSITIVE_INFINITY;
   return (
     <input
       class="w-20 rounded-md border-0 bg-secondary py-1.5 shadow-sm sm:text-sm/6"
       type="number"
       min={min()}
+      max={props.max}
       value={props.value}
       onInput={(e) => {
         const value = Number.parseInt(e.target.value, 10);
-        if (Number.isNaN(value) || value < min()) {
+        if (Number.isNaN(value) || value < min() || value > max()) {
           return;
         }
         props.onChange(value);
@@ -216,7 +219,7 @@ function SettingsNumberInput(props: {
         const value = Number.parseInt(e.currentTarget.value, 10);
         const clamped = Number.isNaN(value)
           ? props.value
-          : Math.max(min(), value);
+          : Math.min(max(), Math.max(min(), value));
         e.currentTarget.value = clamped.toString();
         props.onChange(clamped);
       }}
@@ -46,7 +46,10 @@ async function resetSettings() {
 }
 
 async function saveSettings() {
-  await commands.saveSettings(settings);
+  const result = await commands.saveSettings(settings);
+  if (result.status === "error") {
+    addToast({ message: result.error, type: "error" });
+  }
 }
 
 const debounceSaveSettings = debounce(saveSettings, 500);
@@ -1,29 +1,43 @@
-import { createStore } from "solid-js/store";
-import { commands, type FileEntry } from "./bindings";
-import { compressImage } from "./compress";
-import { clearFilesListener } from "./listeners";
-import { getProfileActive, settings } from "./settings/settingsData";
-import { Semaphore } from "./utils";
+import { createStore, produce } from "solid-js/store";
+import {
+  commands,
+  type FileEntry,
+  type JobProgressEvent,
+  type JobResultEvent,
+} from "./bindings";
+import {
+  clearFilesListener,
+  jobProgressListener,
+  jobResultListener,
+} from "./listeners";
+import { getProfileActive } from "./settings/settingsData";
 
-clearFilesListener(clearFiles);
-
-const CPU_COUNT = await commands.getCpuCount();
-const semaphore = new Semaphore(0);
-syncSemaphore();
+// The queue holds its files until these are attached
+await Promise.all([
+  clearFilesListener(clearFiles),
+  jobProgressListener(updateProgress),
+  jobResultListener(finishFile),
+]);
 
 type ReadonlyFileEntry = Readonly<FileEntry>;
 
+// Queued files by job ID, so results and cancellations find their file
+const jobs = new Map<number, string>();
+
 interface Store {
   files: ReadonlyFileEntry[];
+  // What each compressing file is doing, by path
+  progress: Record<string, string>;
+  paused: boolean;
 }
 
 const [store, setStore] = createStore<Store>({
   files: [],
+  progress: {},
+  paused: false,
 });
 
-function syncSemaphore() {
-  semaphore.maxConcurrent = settings.threads || CPU_COUNT;
-}
+await resumeQueue();
 
 function newFileEntry(
   path: string,
@@ -38,74 +52,145 @@ function newFileEntry(
     ext: data.ext ?? "",
     error: data.error ?? null,
     savings: data.savings ?? null,
+    root: data.root ?? null,
   };
 }
 
-async function addFile(path: string) {
-  if (store.files.find((f) => f.path === path)) {
+/**
+ * Show the files left in the backend's queue from the last session, then
+ * let it start on them.
+ */
+async function resumeQueue() {
+  const queue = await commands.getQueue();
+  if (queue.status === "error") {
+    console.log(queue.error);
     return;
   }
+  setStore("paused", queue.data.paused);
+  for (const queued of queue.data.files) {
+    jobs.set(queued.jobId, queued.path);
+    const file = newFileEntry(queued.path, { root: queued.root });
+    setStore("files", (f) => [...f, file]);
+    loadFileInfo(file);
+  }
+  updateDockBadge();
+  const started = await commands.startQueue();
+  if (started.status === "error") {
+    console.log(started.error);
+  }
+}
 
-  let file = newFileEntry(path, {});
-  setStore("files", (f) => [...f, file]);
-
-  const fileResult = await commands.getFileInfo(path);
+async function loadFileInfo(
+  file: ReadonlyFileEntry,
+): Promise<ReadonlyFileEntry | null> {
+  const fileResult = await commands.getFileInfo(file.path);
   if (fileResult.status === "error") {
     console.log(fileResult.error);
     updateFile(file, { error: fileResult.error, status: "Error" });
-    return;
+    return null;
   }
-
-  const update: Partial<FileEntry> = {
+  return updateFile(file, {
     file: fileResult.data.filename,
     ext: fileResult.data.extension,
     originalSize: fileResult.data.size,
-  };
-  file = updateFile(file, update);
-  updateDockBadge();
-  await semaphore.acquire();
-  syncSemaphore();
-  try {
-    // The file may have been removed or cleared while waiting in the queue
-    if (store.files.some((f) => f.path === file.path)) {
-      await compressFile(file);
-    }
-  } finally {
-    semaphore.release();
-    updateDockBadge();
+  });
+}
+
+async function addFile(path: string, root: string | null = null) {
+  if (store.files.find((f) => f.path === path)) {
+    return;
   }
+
+  const entry = newFileEntry(path, { root });
+  setStore("files", (f) => [...f, entry]);
+  const file = await loadFileInfo(entry);
+  if (!file) {
+    return;
+  }
+  const queued = await commands.enqueueFile(path, root, getProfileActive());
+  if (queued.status === "error") {
+    updateFile(file, { error: queued.error, status: "Error" });
+    return;
+  }
+  // The file may have been removed or cleared while it was being queued
+  const current = store.files.find((f) => f.path === path);
+  if (!current) {
+    commands.cancelJob(queued.data.jobId);
+    return;
+  }
+  // A fast worker can finish the file before this; failures keep their ID
+  // so they can be retried
+  if (current.status !== "Complete" && current.status !== "AlreadySmaller") {
+    jobs.set(queued.data.jobId, path);
+  }
+  updateDockBadge();
 }
 
-async function compressFile(_file: FileEntry) {
-  let file = updateFile(_file, { status: "Compressing" });
-  const parallelImages = semaphore.currentRunning;
-  const compressResult = await compressImage(
-    getProfileActive(),
-    file,
-    parallelImages,
-  );
-  if (compressResult.status === "error") {
-    if (compressResult.error.errorType === "NotSmaller") {
+function finishFile(event: JobResultEvent) {
+  // The result can arrive before `addFile` knows the job ID
+  const path = jobs.get(event.jobId) ?? event.path;
+  const file = store.files.find((f) => f.path === path);
+  if (!file) {
+    return;
+  }
+  if (event.error) {
+    const errorType = event.error.errorType;
+    // Only files that were removed from the list are cancelled
+    if (errorType === "Cancelled") {
+      return;
+    }
+    if (errorType === "NotSmaller" || errorType === "Skipped") {
+      jobs.delete(event.jobId);
       updateFile(file, {
-        error: compressResult.error.error,
+        error: event.error.error,
         status: "AlreadySmaller",
       });
-      return;
+    } else {
+      // Kept so the file can be retried
+      updateFile(file, { error: event.error.error, status: "Error" });
     }
-    updateFile(file, { error: compressResult.error.error, status: "Error" });
+    updateDockBadge();
+    return;
+  }
+  jobs.delete(event.jobId);
+  if (!event.result) {
     return;
   }
 
-  const outSize = compressResult.data.outSize;
+  const outSize = event.result.outSize;
   let savings = null;
   if (file.originalSize !== null) {
     savings = ((file.originalSize - outSize) / file.originalSize) * 100;
   }
-  file = updateFile(file, {
+  updateFile(file, {
     status: "Complete",
     size: outSize,
     savings,
   });
+  updateDockBadge();
+}
+
+function updateProgress(event: JobProgressEvent) {
+  const path = jobs.get(event.jobId);
+  if (path === undefined) {
+    return;
+  }
+  const { stage } = event;
+  if (stage.type === "Done") {
+    setStore(produce((s) => delete s.progress[path]));
+    return;
+  }
+  const file = store.files.find((f) => f.path === path);
+  if (file && file.status !== "Compressing") {
+    updateFile(file, { status: "Compressing" });
+  }
+  // Counts outputs, as encoders don't report how far along they are
+  const output =
+    stage.type === "Encoding" && stage.outputs > 1
+      ? ` output ${stage.output} of ${stage.outputs}`
+      : "";
+  const seconds = (event.elapsedMs / 1000).toFixed(1);
+  setStore("progress", path, `${stage.type}${output} (${seconds}s)`);
 }
 
 function updateFile(
@@ -117,7 +202,7 @@ function updateFile(
   return newFile;
 }
 
-function isFileDone(file: ReadonlyFileEntry): boolean {
+export function isFileDone(file: ReadonlyFileEntry): boolean {
   return (
     file.status === "Complete" ||
     file.status === "AlreadySmaller" ||
@@ -135,14 +220,64 @@ function updateDockBadge() {
   }
 }
 
+function jobIdOf(path: string): number | undefined {
+  return [...jobs].find(([, jobPath]) => jobPath === path)?.[0];
+}
+
+/** Queue a failed file again. */
+async function retryFile(path: string) {
+  const file = store.files.find((f) => f.path === path);
+  const jobId = jobIdOf(path);
+  if (!file || file.status !== "Error" || jobId === undefined) {
+    return;
+  }
+  const retried = await commands.retryJob(jobId);
+  if (retried.status === "ok") {
+    updateFile(file, { status: "Processing", error: null });
+    updateDockBadge();
+  }
+}
+
+/** Process a queued file before the others. */
+function prioritizeFile(path: string) {
+  const jobId = jobIdOf(path);
+  if (jobId !== undefined) {
+    commands.setJobPriority(jobId, 1);
+  }
+}
+
+async function setPaused(paused: boolean) {
+  const result = await commands.setQueuePaused(paused);
+  if (result.status === "ok") {
+    setStore("paused", paused);
+  }
+}
+
 function clearFiles() {
-  semaphore.cancel();
+  jobs.clear();
   setStore("files", []);
+  setStore(produce((s) => (s.progress = {})));
+  commands.clearQueue();
+  commands.clearBatch();
   commands.setDockBadge(0);
 }
 
 function removeFile(path: string) {
+  const jobId = jobIdOf(path);
+  if (jobId !== undefined) {
+    jobs.delete(jobId);
+    commands.cancelJob(jobId);
+  }
   setStore("files", (f) => f.filter((f) => f.path !== path));
 }
 
-export { store, addFile, updateFile, clearFiles, removeFile };
+export {
+  store,
+  addFile,
+  updateFile,
+  clearFiles,
+  removeFile,
+  retryFile,
+  prioritizeFile,
+  setPaused,
+};